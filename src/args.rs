@@ -2,7 +2,10 @@ use std::time::Duration;
 
 use crate::{
     constants::*,
-    models::{Grid, MetadataPosition, TimestampPosition},
+    models::{
+        AudioView, CaptureMode, Deinterlace, Executables, FrameRate, Grid, MetadataPosition,
+        TimestampBase, TimestampPosition, Tonemap,
+    },
 };
 use clap::Parser;
 use humantime::DurationError;
@@ -29,31 +32,79 @@ pub struct Args {
     #[arg(long, short = 'S')]
     pub actual_size: bool,
 
+    /// Detect black letterbox and pillarbox bars from a few sample frames and crop them from every capture.
+    #[arg(long)]
+    pub autocrop: bool,
+
+    /// What is drawn in each cell for files with audio but no video: the 'waveform', the 'spectrogram' or 'both' stacked.
+    #[arg(long, default_value = "both", value_enum)]
+    pub audio_view: AudioView,
+
     /// Color of the timestamp background rectangle in hexadecimal, for example AABBCC
     #[arg(long, default_value = "39897eff", required = false)]
     pub background_colour: String,
 
+    /// Draw the subtitle cue shown at each capture onto it. Subtitles are read from --subtitle-file, --subtitle-stream, a .srt, .ass, .ssa or .vtt file next to the video, or else the first embedded text subtitle stream.
+    #[arg(long)]
+    pub burn_subtitles: bool,
+
+    /// Directory probes and captures are cached in. Defaults to a vcsr directory in the user's cache directory.
+    #[arg(long, env = "VCSR_CACHE_DIR")]
+    pub cache_dir: Option<String>,
+
+    /// Size in MiB the cache is kept under. Once every file is processed, the entries of the files used least recently are removed. Zero disables the limit.
+    #[arg(long, default_value = "1024")]
+    pub cache_size: u64,
+
     /// Alpha channel value for the captures (transparency in range [0, 255]). Defaults to 255 (opaque)
     #[arg(long, default_value = "255", required = false)]
     pub capture_alpha: u8,
 
+    /// How frames are extracted. 'per-timestamp' runs one ffmpeg process per capture, 'single-pass' decodes the file once from the first capture to the last with a single ffmpeg process, which is faster on slow or network storage and for captures close together.
+    #[arg(long, default_value = "per-timestamp", value_enum)]
+    pub capture_mode: CaptureMode,
+
+    /// Number of times a failed capture is retried, each time one second earlier than the previous attempt.
+    #[arg(long, default_value = "2", required = false)]
+    pub capture_retries: u32,
+
+    /// Capture frames from each chapter instead of spacing captures evenly, label them with the chapter title and list the chapters in the header. Falls back to even spacing for files without chapters.
+    #[arg(long)]
+    pub chapters: bool,
+
+    /// Number of captures spaced evenly within each chapter. Used with --chapters only.
+    #[arg(long, default_value = "1", required = false)]
+    pub captures_per_chapter: u64,
+
     /// do not capture frames in the first and last n percent of total time
     #[arg(long)]
     pub delay_percent: Option<f32>,
 
+    /// Deinterlace captures to remove combing. 'auto' deinterlaces videos whose field order says they are interlaced, 'on' deinterlaces every video and 'off' never does.
+    #[arg(long, default_value = "auto", value_enum)]
+    pub deinterlace: Deinterlace,
+
     /// do not capture frames in the last n percent of total time
     #[arg(long, default_value = "7", required = false)]
     pub end_delay_percent: f32,
 
-    /// Do not process files that end with the given extensions.
+    /// Do not process files that end with the given extensions. Numbered images in a directory are read as an image sequence whatever their extension.
     #[arg(long, default_values_t = vec![("jpg").to_string()])]
     pub exclude_extensions: Vec<String>,
 
+    /// ffmpeg executable used for captures.
+    #[arg(long, env = "VCSR_FFMPEG", default_value = "ffmpeg")]
+    pub ffmpeg_path: String,
+
+    /// ffprobe executable used to read media information.
+    #[arg(long, env = "VCSR_FFPROBE", default_value = "ffprobe")]
+    pub ffprobe_path: String,
+
     /// Fast mode. Just make a contact sheet as fast as possible, regardless of output image quality. May mess up the terminal.
     #[arg(long)]
     pub fast: bool,
 
-    /// Frame type passed to ffmpeg 'select=eq(pict_type,FRAME_TYPE)' filter. Should be one of ('I', 'B', 'P') or the special type 'key' which will use the 'select=key' filter instead.
+    /// Frame type passed to ffmpeg 'select=eq(pict_type,FRAME_TYPE)' filter. Should be one of ('I', 'B', 'P') or the special type 'key' which will use the 'select=key' filter instead and move every capture onto its nearest keyframe.
     #[arg(long)]
     pub frame_type: Option<String>,
 
@@ -85,6 +136,10 @@ pub struct Args {
     #[arg(long, short, value_parser = parse_humantime_duration)]
     pub interval: Option<Duration>,
 
+    /// Name shown in the header in place of the filename. Input read from stdin (`-`) or a pipe is also saved under this name, or as `stdin` without one.
+    #[arg(long)]
+    pub label: Option<String>,
+
     /// Space-separated list of frame timestamps to use, for example 1:11:11.111 2:22:22.222
     #[arg(long = "manual", short = 'm', required = false)]
     pub manual_timestamps: Vec<String>,
@@ -125,14 +180,50 @@ pub struct Args {
     #[arg(long)]
     pub no_overwrite: bool,
 
+    /// Probe and capture every file again instead of reusing cached probes and captures.
+    #[arg(long)]
+    pub no_cache: bool,
+
     /// save to output file
     #[arg(long = "output", short = 'o')]
     pub output_path: Option<String>,
 
+    /// Fill the cell of a capture that still fails after every retry with a placeholder instead of skipping the whole file.
+    #[arg(long)]
+    pub placeholder_on_failure: bool,
+
     /// Process every file in the specified directory recursively
     #[arg(long, short)]
     pub recursive: bool,
 
+    /// Pick one frame per shot, detected from scene changes, instead of spacing captures evenly. Falls back to even spacing when there are fewer shots than grid cells.
+    #[arg(long)]
+    pub scenes: bool,
+
+    /// Scene change score (in range [0, 1]) above which a frame starts a new shot. Used with --scenes only.
+    #[arg(long, default_value = "0.3", required = false)]
+    pub scene_threshold: f32,
+
+    /// Frame rate image sequences are read at, which sets the timeline captures are spread over. Either a whole number or a fraction such as 24000/1001.
+    #[arg(long, default_value = "24")]
+    pub sequence_frame_rate: FrameRate,
+
+    /// Subtitle file to burn in. Used with --burn-subtitles only.
+    #[arg(long)]
+    pub subtitle_file: Option<String>,
+
+    /// Size of the font used for burnt in subtitles. Used with --burn-subtitles only.
+    #[arg(long, default_value = "16", required = false)]
+    pub subtitle_font_size: f32,
+
+    /// Index of the embedded subtitle stream to burn in, as listed by ffprobe. Used with --burn-subtitles only.
+    #[arg(long)]
+    pub subtitle_stream: Option<u32>,
+
+    /// Add a line per audio and subtitle track to the header, with its codec, channels, sample rate, bitrate and language
+    #[arg(long)]
+    pub stream_details: bool,
+
     ///number of samples
     #[arg(long, short = 's')]
     pub num_samples: Option<u64>,
@@ -153,10 +244,18 @@ pub struct Args {
     #[arg(long, short = 'O')]
     pub thumbnail_output_path: Option<String>,
 
+    /// Kill any ffmpeg or ffprobe process probing or capturing frames for longer than this. Passes reading the whole file, such as scene detection and the keyframe index, get this plus the duration of the file. The libav backend is not limited. Format is any string supported by `humantime`, for example '90s' or '10m'. Zero disables the timeout.
+    #[arg(long, default_value = "5m", value_parser = parse_humantime_duration)]
+    pub timeout: Duration,
+
     /// Color of the timestamp background rectangle in hexadecimal, for example AABBCC
     #[arg(long, default_value = "000000aa", required = false)]
     pub timestamp_background_colour: String,
 
+    /// Label captures with the time from the start of the file, or with the presentation timestamps stored in it, which differ for files with a non-zero start time such as MPEG-TS. Timestamps given with --manual are always from the start of the file.
+    #[arg(long, default_value = "media", value_enum)]
+    pub timestamp_base: TimestampBase,
+
     /// Color of the timestamp border in hexadecimal, for example AABBCC
     #[arg(long, default_value = "000000", required = false)]
     pub timestamp_border_colour: String,
@@ -212,15 +311,23 @@ pub struct Args {
     #[arg(long, default_value = "1", required = false)]
     pub timestamp_vertical_padding: u64,
 
+    /// Algorithm used to tone map HDR10 and HLG video to SDR so captures are not washed out, or 'off' to capture HDR frames as they are. Needs ffmpeg built with the zscale and tonemap filters.
+    #[arg(long, default_value = "hable", value_enum)]
+    pub tonemap: Tonemap,
+
     /// width of the generated contact sheet
     #[arg(long = "width", short = 'w', default_value = "1500", required = false)]
     pub vcs_width: u64,
 
+    /// Index of the video stream to capture, as listed by ffprobe. Defaults to the first video stream that is not an attached picture such as cover art.
+    #[arg(long)]
+    pub video_stream: Option<u32>,
+
     /// log to stdout as well as to the log file.
     #[arg(long, short)]
     pub verbose: bool,
 
-    /// files to be processed. Clap requires this to be the last argument
+    /// files to be processed, or `-` to read a video from stdin. Clap requires this to be the last argument
     #[arg(required = true)]
     pub filenames: Vec<String>,
 }
@@ -229,6 +336,16 @@ impl Args {
     fn num_samples(grid: Grid) -> Option<u64> {
         Some(grid.x * grid.y)
     }
+
+    /// The ffmpeg and ffprobe executables to run, with the timeout unset
+    /// when it is zero.
+    pub fn executables(&self) -> Executables {
+        Executables {
+            ffmpeg: self.ffmpeg_path.clone(),
+            ffprobe: self.ffprobe_path.clone(),
+            timeout: Some(self.timeout).filter(|timeout| !timeout.is_zero()),
+        }
+    }
 }
 
 impl Default for Args {
@@ -239,17 +356,28 @@ impl Default for Args {
             accurate: false,
             accurate_delay_seconds: DEFAULT_ACCURATE_DELAY_SECONDS,
             background_colour: String::from(DEFAULT_BACKGROUND_COLOUR),
+            burn_subtitles: false,
+            cache_dir: None,
+            cache_size: DEFAULT_CACHE_SIZE,
             actual_size: false,
+            autocrop: false,
+            audio_view: DEFAULT_AUDIO_VIEW,
             capture_alpha: DEFAULT_CAPTURE_ALPHA,
+            capture_mode: DEFAULT_CAPTURE_MODE,
+            capture_retries: DEFAULT_CAPTURE_RETRIES,
+            chapters: false,
+            captures_per_chapter: DEFAULT_CAPTURES_PER_CHAPTER,
+            deinterlace: DEFAULT_DEINTERLACE,
             delay_percent: DEFAULT_DELAY_PERCENT,
             end_delay_percent: DEFAULT_END_DELAY_PERCENT,
             exclude_extensions: vec![
                 String::from("jpg"),
                 String::from("txt"),
                 String::from("srt"),
-                String::from("png"),
             ],
             fast: false,
+            ffmpeg_path: String::from(DEFAULT_FFMPEG_PATH),
+            ffprobe_path: String::from(DEFAULT_FFPROBE_PATH),
             frame_type: DEFAULT_FRAME_TYPE,
             filenames: vec![],
             grid: DEFAULT_GRID_SIZE,
@@ -259,6 +387,7 @@ impl Default for Args {
             image_format: String::from(DEFAULT_IMAGE_FORMAT),
             ignore_errors: false,
             interval: DEFAULT_INTERVAL,
+            label: None,
             manual_timestamps: vec![],
             metadata_background_colour: String::from(DEFAULT_BACKGROUND_COLOUR),
             metadata_font: DEFAULT_METADATA_FONT,
@@ -268,15 +397,26 @@ impl Default for Args {
             metadata_margin: DEFAULT_METADATA_MARGIN,
             metadata_position: DEFAULT_METADATA_POSITION,
             metadata_vertical_margin: DEFAULT_METADATA_VERTICAL_MARGIN,
+            no_cache: false,
             no_overwrite: false,
             output_path: None,
+            placeholder_on_failure: false,
             recursive: false,
+            scenes: false,
+            scene_threshold: DEFAULT_SCENE_THRESHOLD,
+            sequence_frame_rate: DEFAULT_SEQUENCE_FRAME_RATE,
+            stream_details: false,
+            subtitle_file: None,
+            subtitle_font_size: DEFAULT_SUBTITLE_FONT_SIZE,
+            subtitle_stream: None,
             num_samples: None,
             no_shadow: false,
             start_delay_percent: DEFAULT_START_DELAY_PERCENT,
             show_timestamp: true,
             thumbnail_output_path: None,
+            timeout: DEFAULT_TIMEOUT,
             timestamp_background_colour: String::from(DEFAULT_TIMESTAMP_BACKGROUND_COLOUR),
+            timestamp_base: DEFAULT_TIMESTAMP_BASE,
             timestamp_border_colour: String::from(DEFAULT_TIMESTAMP_BORDER_COLOUR),
             timestamp_border_mode: false,
             timestamp_border_radius: 1.0,
@@ -289,7 +429,9 @@ impl Default for Args {
             timestamp_horizontal_padding: DEFAULT_TIMESTAMP_HORIZONTAL_PADDING,
             timestamp_vertical_margin: DEFAULT_TIMESTAMP_VERTICAL_MARGIN,
             timestamp_vertical_padding: DEFAULT_TIMESTAMP_VERTICAL_PADDING,
+            tonemap: DEFAULT_TONEMAP,
             vcs_width: DEFAULT_CONTACT_SHEET_WIDTH,
+            video_stream: None,
             verbose: false,
         }
     }
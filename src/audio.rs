@@ -0,0 +1,183 @@
+//! Contact sheets of audio-only files. Each cell shows the waveform and
+//! spectrogram of the slice of audio around its timestamp in place of a
+//! capture.
+use crate::constants::*;
+use crate::errors::VcsrError;
+use crate::models::{AudioView, FrameSource, MediaCapture};
+
+use image::{Rgba, RgbaImage};
+use rustfft::{num_complex::Complex, FftPlanner};
+
+/// A frame source drawing audio stream `stream` of a file. Each capture
+/// decodes only its slice of the audio, as mono samples at
+/// `AUDIO_SAMPLE_RATE`, so long files are never held in memory.
+pub struct AudioSource<'a> {
+    capture: &'a MediaCapture,
+    stream: u32,
+    view: AudioView,
+    slice_seconds: f32,
+}
+
+impl<'a> AudioSource<'a> {
+    /// Draw `view` of the `slice_seconds` of audio around each capture
+    /// time.
+    pub fn new(
+        capture: &'a MediaCapture,
+        stream: u32,
+        view: AudioView,
+        slice_seconds: f32,
+    ) -> AudioSource<'a> {
+        AudioSource {
+            capture,
+            stream,
+            view,
+            slice_seconds,
+        }
+    }
+
+    /// Every setting that changes what is drawn, so cached cells are only
+    /// reused with the same settings.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "audio stream={} rate={} view={:?} slice={}",
+            self.stream, AUDIO_SAMPLE_RATE, self.view, self.slice_seconds
+        )
+    }
+}
+
+impl<'a> FrameSource for AudioSource<'a> {
+    fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+        let start = (time - self.slice_seconds / 2.0).max(0.0);
+        let end = time + self.slice_seconds / 2.0;
+        let samples =
+            self.capture
+                .audio_samples(self.stream, AUDIO_SAMPLE_RATE, start, end - start)?;
+        let slice = samples.as_slice();
+
+        let mut image = RgbaImage::from_pixel(width as u32, height as u32, AUDIO_BACKGROUND);
+        let height = height as u32;
+        match self.view {
+            AudioView::Waveform => draw_waveform(&mut image, slice, 0, height),
+            AudioView::Spectrogram => draw_spectrogram(&mut image, slice, 0, height),
+            AudioView::Both => {
+                let split = height * 2 / 5;
+                draw_waveform(&mut image, slice, 0, split);
+                draw_spectrogram(&mut image, slice, split, height - split);
+            }
+        }
+        Ok(image)
+    }
+}
+
+/// Draw the peaks of `samples` into `height` rows of `image` from `top`,
+/// one column of samples per pixel column.
+fn draw_waveform(image: &mut RgbaImage, samples: &[i16], top: u32, height: u32) {
+    let width = image.width() as usize;
+    if samples.is_empty() || height == 0 {
+        return;
+    }
+    let centre = top as f32 + height as f32 / 2.0;
+    let scale = height as f32 / 2.0 / f32::from(i16::MAX);
+    for x in 0..width {
+        let column = &samples[x * samples.len() / width..(x + 1) * samples.len() / width];
+        let (min, max) = column.iter().fold((0, 0), |(min, max), sample| {
+            (min.min(*sample), max.max(*sample))
+        });
+        let to_row = |sample: i16| {
+            ((centre - f32::from(sample) * scale) as u32).clamp(top, top + height - 1)
+        };
+        for y in to_row(max)..=to_row(min) {
+            image.put_pixel(x as u32, y, WAVEFORM_COLOUR);
+        }
+    }
+}
+
+/// Draw the spectrum of `samples` into `height` rows of `image` from
+/// `top`, with low frequencies at the bottom and louder frequencies
+/// brighter.
+fn draw_spectrogram(image: &mut RgbaImage, samples: &[i16], top: u32, height: u32) {
+    let width = image.width() as usize;
+    if samples.is_empty() || height == 0 {
+        return;
+    }
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(SPECTROGRAM_WINDOW);
+    let bins = SPECTROGRAM_WINDOW / 2;
+    // A Hann window keeps loud frequencies from smearing into their
+    // neighbours.
+    let window: Vec<f32> = (0..SPECTROGRAM_WINDOW)
+        .map(|i| {
+            let phase = 2.0 * std::f32::consts::PI * i as f32 / SPECTROGRAM_WINDOW as f32;
+            0.5 - 0.5 * phase.cos()
+        })
+        .collect();
+    let full_scale = bins as f32 * f32::from(i16::MAX);
+
+    for x in 0..width {
+        let centre = (2 * x + 1) * samples.len() / (2 * width);
+        let start = centre.saturating_sub(SPECTROGRAM_WINDOW / 2);
+        let mut buffer: Vec<Complex<f32>> = window
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| {
+                let sample = samples.get(start + i).copied().unwrap_or(0);
+                Complex::new(f32::from(sample) * weight, 0.0)
+            })
+            .collect();
+        fft.process(&mut buffer);
+
+        for row in 0..height {
+            let bin = (height - 1 - row) as usize * bins / height as usize;
+            let decibels = 20.0 * (buffer[bin].norm() / full_scale + 1e-9).log10();
+            let level = (decibels / SPECTROGRAM_RANGE_DB + 1.0).clamp(0.0, 1.0);
+            image.put_pixel(x as u32, top + row, heat_colour(level));
+        }
+    }
+}
+
+/// Colour of a spectrogram `level` between 0 and 1, going from black
+/// through purple and orange to pale yellow.
+fn heat_colour(level: f32) -> Rgba<u8> {
+    const STOPS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [110.0, 30.0, 140.0],
+        [240.0, 110.0, 30.0],
+        [255.0, 245.0, 170.0],
+    ];
+    let position = level * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let fraction = position - index as f32;
+    let channel =
+        |c: usize| (STOPS[index][c] + (STOPS[index + 1][c] - STOPS[index][c]) * fraction) as u8;
+    Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveform_and_spectrogram_fill_their_rows() {
+        // A loud 1 kHz tone.
+        let samples: Vec<i16> = (0..AUDIO_SAMPLE_RATE)
+            .map(|i| {
+                let phase = 2.0 * std::f32::consts::PI * 1000.0 * i as f32;
+                ((phase / AUDIO_SAMPLE_RATE as f32).sin() * 20000.0) as i16
+            })
+            .collect();
+
+        let mut image = RgbaImage::from_pixel(64, 40, AUDIO_BACKGROUND);
+        draw_waveform(&mut image, &samples, 0, 40);
+        assert_eq!(*image.get_pixel(10, 20), WAVEFORM_COLOUR);
+        assert_eq!(*image.get_pixel(10, 1), AUDIO_BACKGROUND);
+
+        let mut image = RgbaImage::from_pixel(64, 40, AUDIO_BACKGROUND);
+        draw_spectrogram(&mut image, &samples, 0, 40);
+        // 1 kHz is a quarter of the 4 kHz shown, so a quarter of the way up.
+        let tone = image.get_pixel(10, 29);
+        let silence = image.get_pixel(10, 5);
+        assert!(tone[0] > silence[0]);
+
+        assert_eq!(heat_colour(0.0), Rgba([0, 0, 0, 255]));
+        assert_eq!(heat_colour(1.0), Rgba([255, 245, 170, 255]));
+    }
+}
@@ -0,0 +1,399 @@
+//! An on-disk cache of probes and captures, so re-running vcsr with
+//! different styling only composes the contact sheet again.
+//!
+//! Entries live in a directory per media file, named after a hash of its
+//! path, size and modification time, so changed files are probed again.
+//! Captures are kept below that in a directory per set of capture settings.
+//! The hash also covers `CACHE_VERSION`, the backend and its version, so
+//! entries written by other versions of vcsr or FFmpeg are not read. `prune` keeps the cache under a
+//! size by removing the entries of the files used least recently.
+use crate::constants::CACHE_VERSION;
+use crate::errors::VcsrError;
+use crate::models::FrameSource;
+use image::{ImageFormat, RgbaImage};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// File in each entry touched whenever the entry is opened, so pruning
+/// removes the entries used least recently.
+const LAST_USED: &str = "last-used";
+
+/// Cached entries for a single media file.
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// The cache for `media` under `root`, or the user's cache directory
+    /// when no root is given, for captures made with FFmpeg `version`.
+    /// Returns `None` when there is no cache directory or `media` cannot be
+    /// read.
+    pub fn open(root: Option<&Path>, media: &Path, version: &str) -> Option<Cache> {
+        let root = Self::root(root)?;
+        let metadata = fs::metadata(media).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        let path = fs::canonicalize(media).unwrap_or_else(|_| media.to_path_buf());
+        let key = format!(
+            "{}|{}|{}|{}|{}|{}",
+            CACHE_VERSION,
+            if cfg!(feature = "libav") {
+                "libav"
+            } else {
+                "ffmpeg"
+            },
+            version,
+            path.display(),
+            metadata.len(),
+            modified
+        );
+        let dir = root.join(hash(&key));
+        if dir.is_dir() {
+            if let Err(err) = fs::write(dir.join(LAST_USED), b"") {
+                debug!("cannot mark cache entry used: {}", err);
+            }
+        }
+        Some(Cache { dir })
+    }
+
+    /// `root`, or the user's cache directory when no root is given.
+    fn root(root: Option<&Path>) -> Option<PathBuf> {
+        match root {
+            Some(root) => Some(root.to_path_buf()),
+            None => Some(dirs::cache_dir()?.join("vcsr")),
+        }
+    }
+
+    /// Remove the entries of the files used least recently from the cache
+    /// under `root` until it takes at most `max_bytes`.
+    pub fn prune(root: Option<&Path>, max_bytes: u64) -> Result<(), VcsrError> {
+        let root = match Self::root(root) {
+            Some(root) if root.is_dir() => root,
+            _ => return Ok(()),
+        };
+        let mut entries = vec![];
+        for entry in fs::read_dir(&root)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                let (size, used) = usage(&path);
+                entries.push((used, size, path));
+            }
+        }
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(used, _, _)| *used);
+        for (_, size, path) in entries {
+            if total <= max_bytes {
+                break;
+            }
+            debug!("pruning cache entry {}", path.display());
+            fs::remove_dir_all(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+
+    /// Entries for the same file read another way, such as an image
+    /// sequence read at another frame rate.
+    pub fn scoped(self, scope: &str) -> Cache {
+        Cache {
+            dir: self.dir.join(hash(scope)),
+        }
+    }
+
+    /// The cached ffprobe JSON, if the file was probed before.
+    pub fn probe(&self) -> Option<String> {
+        fs::read_to_string(self.dir.join("probe.json")).ok()
+    }
+
+    pub fn store_probe(&self, json: &str) {
+        if let Err(err) = write(&self.dir.join("probe.json"), json.as_bytes()) {
+            warn!("cannot cache probe: {}", err);
+        }
+    }
+
+    /// Read the probe from the cache, or run `probe` and cache its result.
+    pub fn probe_or_else<F>(&self, probe: F) -> Result<String, VcsrError>
+    where
+        F: FnOnce() -> Result<String, VcsrError>,
+    {
+        if let Some(json) = self.probe() {
+            debug!("using cached probe from {}", self.dir.display());
+            return Ok(json);
+        }
+        let json = probe()?;
+        self.store_probe(&json);
+        Ok(json)
+    }
+
+    /// Read the duration of stream `stream` recovered from its packets from
+    /// the cache, or run `scan` and cache its result.
+    pub fn duration_or_else<F>(&self, stream: u32, scan: F) -> Result<f32, VcsrError>
+    where
+        F: FnOnce() -> Result<f32, VcsrError>,
+    {
+        let path = self.dir.join(format!("duration-{}.json", stream));
+        if let Some(duration) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+        {
+            return Ok(duration);
+        }
+        let duration = scan()?;
+        if let Err(err) = serde_json::to_vec(&duration)
+            .map_err(VcsrError::from)
+            .and_then(|json| write(&path, &json))
+        {
+            warn!("cannot cache duration: {}", err);
+        }
+        Ok(duration)
+    }
+
+    /// Wrap `source` so its captures, keyframes and scene changes are
+    /// cached under `capture_key`.
+    pub fn frame_source<'a>(
+        &self,
+        source: &'a dyn FrameSource,
+        capture_key: &str,
+    ) -> CachedSource<'a> {
+        CachedSource {
+            inner: source,
+            dir: self.dir.join(hash(capture_key)),
+        }
+    }
+}
+
+/// A frame source reading captures from the cache and capturing only
+/// those missing from it.
+pub struct CachedSource<'a> {
+    inner: &'a dyn FrameSource,
+    dir: PathBuf,
+}
+
+impl<'a> CachedSource<'a> {
+    fn capture_path(&self, time: f32, width: u64, height: u64) -> PathBuf {
+        let millis = (time * 1000.0).round() as u64;
+        self.dir
+            .join(format!("{}-{}x{}.png", millis, width, height))
+    }
+
+    fn cached_capture(&self, time: f32, width: u64, height: u64) -> Option<RgbaImage> {
+        let image = image::open(self.capture_path(time, width, height))
+            .ok()?
+            .into_rgba8();
+        if image.dimensions() == (width as u32, height as u32) {
+            Some(image)
+        } else {
+            None
+        }
+    }
+
+    fn store_capture(&self, time: f32, width: u64, height: u64, image: &RgbaImage) {
+        let path = self.capture_path(time, width, height);
+        let result = fs::create_dir_all(&self.dir)
+            .map_err(VcsrError::from)
+            .and_then(|_| {
+                let partial = path.with_extension(format!("{}.partial", std::process::id()));
+                image.save_with_format(&partial, ImageFormat::Png)?;
+                Ok(fs::rename(&partial, &path)?)
+            });
+        if let Err(err) = result {
+            debug!("cannot cache capture: {}", err);
+        }
+    }
+
+    /// Read `name` from the cache, or compute it with `compute` and cache
+    /// it.
+    fn cached_times<F>(&self, name: &str, compute: F) -> Result<Vec<f32>, VcsrError>
+    where
+        F: FnOnce() -> Result<Vec<f32>, VcsrError>,
+    {
+        let path = self.dir.join(name);
+        if let Some(times) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+        {
+            return Ok(times);
+        }
+        let times = compute()?;
+        if let Err(err) = serde_json::to_vec(&times)
+            .map_err(VcsrError::from)
+            .and_then(|json| write(&path, &json))
+        {
+            warn!("cannot cache {}: {}", name, err);
+        }
+        Ok(times)
+    }
+}
+
+impl<'a> FrameSource for CachedSource<'a> {
+    fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+        if let Some(image) = self.cached_capture(time, width, height) {
+            return Ok(image);
+        }
+        let image = self.inner.capture(time, width, height)?;
+        self.store_capture(time, width, height, &image);
+        Ok(image)
+    }
+
+    fn capture_all(
+        &self,
+        times: &[f32],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        let mut images: Vec<Option<RgbaImage>> = times
+            .iter()
+            .map(|time| self.cached_capture(*time, width, height))
+            .collect();
+        let missing: Vec<f32> = times
+            .iter()
+            .zip(&images)
+            .filter(|(_, image)| image.is_none())
+            .map(|(time, _)| *time)
+            .collect();
+        if !missing.is_empty() {
+            let mut captured = self.inner.capture_all(&missing, width, height)?.into_iter();
+            for (time, image) in times.iter().zip(images.iter_mut()) {
+                if image.is_none() {
+                    let capture = captured.next().ok_or(VcsrError::NoneError)?;
+                    self.store_capture(*time, width, height, &capture);
+                    *image = Some(capture);
+                }
+            }
+        }
+        Ok(images.into_iter().flatten().collect())
+    }
+
+    fn keyframes(&self) -> Result<Vec<f32>, VcsrError> {
+        self.cached_times("keyframes.json", || self.inner.keyframes())
+    }
+
+    fn scene_changes(&self, threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        self.cached_times(&format!("scenes-{}.json", threshold), || {
+            self.inner.scene_changes(threshold)
+        })
+    }
+}
+
+/// Total size of the files under `dir` and when the newest was modified.
+fn usage(dir: &Path) -> (u64, SystemTime) {
+    let mut size = 0;
+    let mut used = UNIX_EPOCH;
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                let (dir_size, dir_used) = usage(&entry.path());
+                size += dir_size;
+                used = used.max(dir_used);
+            } else {
+                size += metadata.len();
+                used = used.max(metadata.modified().unwrap_or(UNIX_EPOCH));
+            }
+        }
+    }
+    (size, used)
+}
+
+/// Write `contents` to `path`, creating its directory, without leaving a
+/// partial file behind if interrupted.
+fn write(path: &Path, contents: &[u8]) -> Result<(), VcsrError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// 64-bit FNV-1a hash of `key` in hex. Unlike `DefaultHasher` its output
+/// is the same across Rust releases, so cache entries stay valid.
+fn hash(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingSource {
+        captures: AtomicUsize,
+    }
+
+    impl FrameSource for CountingSource {
+        fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+            self.captures.fetch_add(1, Ordering::SeqCst);
+            let shade = time as u8;
+            Ok(RgbaImage::from_pixel(
+                width as u32,
+                height as u32,
+                image::Rgba([shade, shade, shade, 255]),
+            ))
+        }
+    }
+
+    #[test]
+    fn cached_source_captures_each_frame_once() {
+        let root = std::env::temp_dir().join(format!("vcsr-cache-test-{}", std::process::id()));
+        let media = root.join("video.mkv");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&media, b"video").unwrap();
+
+        let cache = Cache::open(Some(&root), &media, "7.1").unwrap();
+        assert_eq!(cache.probe(), None);
+        let json = cache.probe_or_else(|| Ok(String::from("{}"))).unwrap();
+        assert_eq!(json, "{}");
+        assert_eq!(cache.probe().as_deref(), Some("{}"));
+
+        let source = CountingSource {
+            captures: AtomicUsize::new(0),
+        };
+        let cached = cache.frame_source(&source, "settings");
+        cached.capture_all(&[10.0, 20.0], 4, 2).unwrap();
+        let images = cached.capture_all(&[10.0, 20.0, 30.0], 4, 2).unwrap();
+        assert_eq!(source.captures.load(Ordering::SeqCst), 3);
+        assert_eq!(images[2].get_pixel(0, 0)[0], 30);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn prune_removes_least_recently_used() {
+        let root = std::env::temp_dir().join(format!("vcsr-prune-test-{}", std::process::id()));
+        let old = root.join("old");
+        let new = root.join("new");
+        fs::create_dir_all(&old).unwrap();
+        fs::create_dir_all(&new).unwrap();
+        fs::write(old.join("probe.json"), [0; 100]).unwrap();
+        fs::write(new.join("probe.json"), [0; 100]).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(old.join("probe.json"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+
+        Cache::prune(Some(&root), 200).unwrap();
+        assert!(old.is_dir() && new.is_dir());
+        Cache::prune(Some(&root), 150).unwrap();
+        assert!(!old.exists() && new.is_dir());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
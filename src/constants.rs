@@ -1,17 +1,66 @@
-use crate::models::{Grid, MetadataPosition, TimestampPosition};
+use crate::models::{
+    AudioView, CaptureMode, Deinterlace, FrameRate, Grid, MetadataPosition, TimestampBase,
+    TimestampPosition, Tonemap,
+};
 use std::time::Duration;
 pub const DEFAULT_ACCURATE_DELAY_SECONDS: f32 = 1.0;
+/// Background of cells drawn for audio-only files.
+pub const AUDIO_BACKGROUND: image::Rgba<u8> = image::Rgba([20, 20, 24, 255]);
+/// Size the cells of audio-only files are laid out as.
+pub const AUDIO_CELL_HEIGHT: u64 = 720;
+pub const AUDIO_CELL_WIDTH: u64 = 1280;
+/// Rate audio is decoded at for drawing, high enough for speech and most
+/// of the energy of music.
+pub const AUDIO_SAMPLE_RATE: u32 = 8000;
+pub const DEFAULT_AUDIO_VIEW: AudioView = AudioView::Both;
 pub const DEFAULT_BACKGROUND_COLOUR: &str = "39897eff";
+/// Version of the cache layout and of what is cached, such as the fields
+/// probed. Bumping it leaves older entries unused until they are pruned.
+pub const CACHE_VERSION: u32 = 2;
+/// Size in MiB the cache is pruned to.
+pub const DEFAULT_CACHE_SIZE: u64 = 1024;
 pub const DEFAULT_CAPTURE_ALPHA: u8 = 255;
+pub const DEFAULT_CAPTURE_RETRIES: u32 = 2;
+pub const CAPTURE_RETRY_NUDGE_SECONDS: f32 = 1.0;
+pub const DEFAULT_CAPTURES_PER_CHAPTER: u64 = 1;
+pub const DEFAULT_CAPTURE_MODE: CaptureMode = CaptureMode::PerTimestamp;
 pub const DEFAULT_CONTACT_SHEET_WIDTH: u64 = 1500;
+/// Number of frames sampled to detect black bars with --autocrop.
+pub const CROP_DETECT_SAMPLES: u64 = 6;
+/// Mean luma at or below which an edge row or column counts as a black bar.
+pub const CROP_DETECT_LIMIT: u8 = 24;
+/// Width frames are sampled at to detect black bars.
+pub const CROP_DETECT_WIDTH: u64 = 640;
+pub const DEFAULT_DEINTERLACE: Deinterlace = Deinterlace::Auto;
 pub const DEFAULT_DELAY_PERCENT: Option<f32> = None;
+/// How far, as a percentage of the longer, the container and video stream
+/// durations may differ before the packets are scanned for the duration.
+pub const DURATION_MISMATCH_PERCENT: f32 = 5.0;
 pub const DEFAULT_END_DELAY_PERCENT: f32 = DEFAULT_START_DELAY_PERCENT;
+pub const DEFAULT_FFMPEG_PATH: &str = "ffmpeg";
+pub const DEFAULT_FFPROBE_PATH: &str = "ffprobe";
 pub const DEFAULT_FRAME_TYPE: Option<String> = None;
 pub const DEFAULT_GRID_HORIZONTAL_SPACING: u64 = 15;
 pub const DEFAULT_GRID_SIZE: Grid = Grid { x: 4, y: 4 };
 pub const DEFAULT_GRID_SPACING: Option<u64> = None;
 pub const DEFAULT_GRID_VERTICAL_SPACING: u64 = DEFAULT_GRID_HORIZONTAL_SPACING;
+/// Transfer characteristics of HDR10 (PQ) and HLG video.
+pub const HDR_TRANSFERS: [&str; 2] = ["smpte2084", "arib-std-b67"];
+/// Field orders ffprobe reports for interlaced video.
+pub const INTERLACED_FIELD_ORDERS: [&str; 4] = ["tt", "bb", "tb", "bt"];
 pub const DEFAULT_IMAGE_FORMAT: &str = "jpg";
+/// Extensions of still images read as numbered image sequences.
+pub const IMAGE_SEQUENCE_EXTENSIONS: [&str; 10] = [
+    "png", "jpg", "jpeg", "tif", "tiff", "exr", "dpx", "bmp", "tga", "webp",
+];
+/// Demuxers of animated images, whose captures are labelled with frame
+/// numbers like image sequences.
+pub const ANIMATED_IMAGE_FORMATS: [&str; 2] = ["gif", "apng"];
+/// Fewest numbered images in a directory taken to be an image sequence.
+pub const IMAGE_SEQUENCE_MIN_FRAMES: usize = 2;
+/// Fewest numbered images in a subdirectory found while walking taken to
+/// be an image sequence, so a few numbered stills are not.
+pub const IMAGE_SEQUENCE_MIN_WALKED_FRAMES: u64 = 24;
 pub const DEFAULT_INTERVAL: Option<Duration> = None;
 pub const METADATA_BACKGROUND_COLOUR: &str = "39897eff";
 pub const DEFAULT_METADATA_FONT: Option<String> = None;
@@ -21,9 +70,28 @@ pub const DEFAULT_METADATA_HORIZONTAL_MARGIN: u64 = DEFAULT_METADATA_MARGIN;
 pub const DEFAULT_METADATA_MARGIN: u64 = 15;
 pub const DEFAULT_METADATA_POSITION: MetadataPosition = MetadataPosition::Top;
 pub const DEFAULT_METADATA_VERTICAL_MARGIN: u64 = DEFAULT_METADATA_MARGIN;
+pub const DEFAULT_SCENE_THRESHOLD: f32 = 0.3;
+/// Samples per spectrogram column, and the quietest level shown below the
+/// loudest possible.
+pub const SPECTROGRAM_WINDOW: usize = 512;
+pub const SPECTROGRAM_RANGE_DB: f32 = 90.0;
+pub const DEFAULT_SEQUENCE_FRAME_RATE: FrameRate = FrameRate {
+    numerator: 24,
+    denominator: 1,
+};
+/// Subtitle codecs whose cues are text that can be drawn onto captures.
+pub const TEXT_SUBTITLE_CODECS: [&str; 6] = ["subrip", "ass", "ssa", "mov_text", "webvtt", "text"];
+/// Extensions of subtitle files picked up next to a video.
+pub const SUBTITLE_EXTENSIONS: [&str; 4] = ["srt", "ass", "ssa", "vtt"];
 pub const FALLBACK_FONTS: &str = "/Library/Fonts/Arial Unicode.ttf";
+pub const DEFAULT_SUBTITLE_FONT_SIZE: f32 = 16.0;
 pub const DEFAULT_START_DELAY_PERCENT: f32 = 7.0;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Seconds the timeout of a pass over the whole file grows by per second
+/// of video, so slow decodes of long files still finish.
+pub const WHOLE_FILE_TIMEOUT_FACTOR: f32 = 1.0;
 pub const DEFAULT_TIMESTAMP_BACKGROUND_COLOUR: &str = "000000aa";
+pub const DEFAULT_TIMESTAMP_BASE: TimestampBase = TimestampBase::Media;
 pub const DEFAULT_TIMESTAMP_BORDER_COLOUR: &str = "000000";
 pub const DEFAULT_TIMESTAMP_BORDER_SIZE: u32 = 1;
 pub const DEFAULT_TIMESTAMP_FONT_COLOUR: &str = "ffffff";
@@ -34,3 +102,5 @@ pub const DEFAULT_TIMESTAMP_HORIZONTAL_PADDING: u64 = 3;
 pub const DEFAULT_TIMESTAMP_POSITION: TimestampPosition = TimestampPosition::SE;
 pub const DEFAULT_TIMESTAMP_VERTICAL_MARGIN: u64 = 5;
 pub const DEFAULT_TIMESTAMP_VERTICAL_PADDING: u64 = 1;
+pub const WAVEFORM_COLOUR: image::Rgba<u8> = image::Rgba([120, 200, 255, 255]);
+pub const DEFAULT_TONEMAP: Tonemap = Tonemap::Hable;
@@ -7,6 +7,16 @@ use thiserror::Error;
 pub enum VcsrError {
     #[error("Arguments are invalid: `{0}`")]
     ArgumentError(String),
+    #[error("Cannot read audio: {0}")]
+    AudioError(String),
+    #[error("Processing was cancelled")]
+    Cancelled,
+    #[error("Capture at {timestamp} failed (exit code {}): {stderr}", .exit_code.map_or(String::from("none"), |code| code.to_string()))]
+    CaptureFailed {
+        timestamp: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
     #[error("Input colours are invalid: `{0}`")]
     ColourError(String),
     #[error("Grid must be of the form mxn, where m is the number of columns and n is the number of rows.")]
@@ -17,16 +27,37 @@ pub enum VcsrError {
     IoError(#[from] std::io::Error),
     #[error(transparent)]
     IntError(#[from] std::num::ParseIntError),
+    #[error(
+        "Cannot run `{0}`, install it or pass its location with --ffmpeg-path and --ffprobe-path"
+    )]
+    ExecutableNotFound(String),
+    #[error("Invalid frame rate: `{0}`")]
+    FrameRateError(String),
     #[error(transparent)]
     FloatError(#[from] std::num::ParseFloatError),
+    #[cfg(feature = "libav")]
+    #[error(transparent)]
+    LibavError(#[from] ffmpeg_next::Error),
+    #[error("ffmpeg {version} does not have the `{filter}` filter needed for {feature}")]
+    MissingFilter {
+        filter: String,
+        feature: String,
+        version: String,
+    },
     #[error("Could not find all media attributes")]
     MediaError,
     #[error("Cannot unwrap none")]
     NoneError,
     #[error("Rust Type Font Error")]
     RustTypeError,
+    #[error("{0} is a still image, not a video")]
+    StillImage(String),
     #[error("Stream Error")]
     StreamError(#[from] serde_json_error::Error),
+    #[error("Cannot read subtitles: {0}")]
+    SubtitleError(String),
+    #[error("`{program}` did not finish within {seconds} seconds and was killed")]
+    Timeout { program: String, seconds: f32 },
     #[error("Invalid timestamps: `{0}`")]
     TimestampError(String),
     #[error("The file does not contain a video stream.")]
@@ -16,14 +16,19 @@ extern crate serde_json;
 extern crate textwrap;
 
 pub mod args;
+pub mod audio;
+pub mod cache;
 mod constants;
 pub mod errors;
 pub mod models;
+#[cfg(feature = "libav")]
+mod native;
 mod operations;
+pub mod process;
 
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::{
-    io,
+    fs, io,
     path::{Path, PathBuf},
     str::FromStr,
 };
@@ -47,34 +52,148 @@ use walkdir::DirEntry;
 //     }
 // }
 
+/// Whether the FFmpeg libraries vcsr is built with have the filter `name`.
+#[cfg(feature = "libav")]
+pub fn has_filter(name: &str) -> bool {
+    native::has_filter(name)
+}
+
 pub fn process_file(
     dir_entry: &DirEntry,
     args: &mut args::Args,
     multi: &MultiProgress,
 ) -> Result<PathBuf, errors::VcsrError> {
-    let file_name_str = dir_entry.file_name().to_str().unwrap();
+    process_input(dir_entry.path(), dir_entry.path(), args, multi)
+}
+
+/// Make a contact sheet of video read from `reader`, such as stdin or a
+/// named pipe, which ffmpeg cannot seek in. The video is spooled to a
+/// temporary file first, and the sheet named after `--label`, `name` or
+/// `stdin`.
+pub fn process_stream<R: io::Read>(
+    reader: &mut R,
+    name: Option<&Path>,
+    args: &mut args::Args,
+    multi: &MultiProgress,
+) -> Result<PathBuf, errors::VcsrError> {
+    spool_stream(reader, name, args, |name, input, args| {
+        process_input(name, input, args, multi)
+    })
+}
+
+/// Spool `reader` and run `process` on the spool, with the name the sheet
+/// is given and the arguments for a spooled stream. The spool is removed
+/// once `process` returns.
+fn spool_stream<R, F>(
+    reader: &mut R,
+    name: Option<&Path>,
+    args: &mut args::Args,
+    process: F,
+) -> Result<PathBuf, errors::VcsrError>
+where
+    R: io::Read,
+    F: FnOnce(&Path, &Path, &mut args::Args) -> Result<PathBuf, errors::VcsrError>,
+{
+    let label = stream_label(args, name);
+    let spool = Spool::new(reader)?;
+    debug!("spooled {} to {}", label, spool.path.display());
+    // A spooled file is new every time, so has nothing to cache under.
+    args.no_cache = true;
+    args.label = Some(label.clone());
+    let name = match name {
+        Some(name) => name.with_file_name(&label),
+        None => PathBuf::from(&label),
+    };
+    process(&name, &spool.path, args)
+}
+
+/// Name of the sheet of a stream: `--label`, the name of the pipe or
+/// `stdin`.
+fn stream_label(args: &args::Args, name: Option<&Path>) -> String {
+    match (&args.label, name.and_then(Path::file_name)) {
+        (Some(label), _) => label.clone(),
+        (None, Some(name)) => name.to_string_lossy().into_owned(),
+        (None, None) => String::from("stdin"),
+    }
+}
+
+/// A temporary copy of a stream, removed when dropped.
+struct Spool {
+    path: PathBuf,
+}
+
+impl Spool {
+    /// Copy `reader` to a new file with a random name in the temporary
+    /// directory. The file must not exist yet, so a file or link planted
+    /// under the name is never written through.
+    fn new<R: io::Read>(reader: &mut R) -> Result<Spool, errors::VcsrError> {
+        let path = std::env::temp_dir().join(format!(
+            "vcsr-spool-{}-{:016x}",
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&path)?;
+        process::add_temp_file(&path);
+        let spool = Spool { path };
+        io::copy(reader, &mut file)?;
+        Ok(spool)
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            debug!("cannot remove {}: {}", self.path.display(), err);
+        }
+        process::forget_temp_file(&self.path);
+    }
+}
+
+/// Make a contact sheet of `input`, named after `name`, which is only
+/// different from `input` for spooled streams.
+fn process_input(
+    name: &Path,
+    input: &Path,
+    args: &mut args::Args,
+    multi: &MultiProgress,
+) -> Result<PathBuf, errors::VcsrError> {
+    let file_name_str = name
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
 
     debug!("Starting process for {}", file_name_str);
 
-    if !dir_entry.path().exists() {
+    if !input.exists() {
         if args.ignore_errors {
             info!("File does not exist, skipping {}: ", file_name_str);
-            return Ok(dir_entry.path().to_path_buf());
+            return Ok(input.to_path_buf());
         } else {
             return Err(std::io::Error::new(io::ErrorKind::NotFound, "file does not exist").into());
         }
     }
 
+    // Image sequence directories are named after the directory, made
+    // absolute so `.` has a name.
+    let input_name = if input.is_dir() {
+        fs::canonicalize(name)?
+    } else {
+        name.to_path_buf()
+    };
     let output_path = match &args.output_path {
         None => {
-            let mut full_path = dir_entry.path().to_path_buf().into_os_string();
+            let mut full_path = input_name.clone().into_os_string();
             full_path.push(format!(".{}", args.image_format));
             PathBuf::from(full_path)
         }
         Some(output_path) => {
             if Path::new(output_path).is_dir() {
                 let mut full_path = Path::new(output_path)
-                    .join(dir_entry.file_name())
+                    .join(input_name.file_name().unwrap_or(name.as_os_str()))
                     .into_os_string();
                 full_path.push(format!(".{}", args.image_format));
                 PathBuf::from(full_path)
@@ -100,6 +219,26 @@ pub fn process_file(
         ));
     }
 
+    if args.scenes && (args.interval.is_some() || !args.manual_timestamps.is_empty()) {
+        return Err(errors::VcsrError::ArgumentError(
+            "Cannot use --scenes with --interval or --manual.".to_string(),
+        ));
+    }
+
+    if args.chapters
+        && (args.scenes || args.interval.is_some() || !args.manual_timestamps.is_empty())
+    {
+        return Err(errors::VcsrError::ArgumentError(
+            "Cannot use --chapters with --scenes, --interval or --manual.".to_string(),
+        ));
+    }
+
+    if args.chapters && args.captures_per_chapter == 0 {
+        return Err(errors::VcsrError::ArgumentError(
+            "--captures-per-chapter must be at least 1.".to_string(),
+        ));
+    }
+
     if args.vcs_width != constants::DEFAULT_CONTACT_SHEET_WIDTH && args.actual_size {
         return Err(errors::VcsrError::ArgumentError(
             "Cannot use --width and --actual-size at the same time.".to_string(),
@@ -113,29 +252,127 @@ pub fn process_file(
 
     args.num_groups = Some(5);
 
-    let media_info = models::MediaInfo::new(dir_entry.path(), false)?;
+    let image_sequence = if input.is_dir() {
+        let sequence = models::ImageSequence::find(input).ok_or_else(|| {
+            errors::VcsrError::ArgumentError(format!("{} has no image sequence", file_name_str))
+        })?;
+        Some(sequence)
+    } else {
+        None
+    };
+    let (input_path, input_options) = match &image_sequence {
+        Some(sequence) => (
+            sequence.pattern(),
+            sequence.input_options(args.sequence_frame_rate),
+        ),
+        None => (input.to_path_buf(), vec![]),
+    };
+
+    let cache = if args.no_cache {
+        None
+    } else {
+        cache::Cache::open(
+            args.cache_dir.as_deref().map(Path::new),
+            input,
+            &args.executables().version(),
+        )
+        .map(|cache| match &image_sequence {
+            // Images rendered again in place leave the directory as it was.
+            Some(sequence) => cache.scoped(&format!(
+                "{:?} {} {}",
+                input_options, sequence.size_bytes, sequence.modified
+            )),
+            None => cache,
+        })
+    };
+    let probe = || models::MediaInfo::probe_json(&input_path, &input_options, &args.executables());
+    let probe_json = match &cache {
+        Some(cache) => cache.probe_or_else(probe)?,
+        None => probe()?,
+    };
+    let media_info = match &image_sequence {
+        Some(sequence) => {
+            models::MediaInfo::from_sequence_json(&probe_json, sequence, args.video_stream)?
+        }
+        None => models::MediaInfo::from_json(&probe_json, args.video_stream)?,
+    };
 
-    let media_attributes = media_info
+    let mut media_attributes = media_info
         .media_attributes
         .ok_or_else(|| errors::VcsrError::MediaError)?;
+    if let Some(label) = &args.label {
+        media_attributes.filename = label.clone();
+    }
     let media_capture = models::MediaCapture::new(
-        dir_entry.path().to_string_lossy().to_owned().to_string(),
+        input_path.to_string_lossy().into_owned(),
         args.accurate,
         args.accurate_delay_seconds,
         args.frame_type.clone(),
-    );
+        media_attributes.video_stream,
+        media_attributes.rotation,
+        args.executables(),
+    )
+    .with_input_options(input_options)
+    .with_start_time(media_attributes.start_time)
+    .with_deinterlace(args.deinterlace.applies(media_attributes.interlaced))
+    .with_tonemap(if media_attributes.hdr {
+        args.tonemap
+    } else {
+        models::Tonemap::Off
+    });
+
+    // Missing or doubtful durations are recovered from the packets, so
+    // captures are not placed past the end of truncated recordings.
+    if media_attributes.duration_source.is_none() || media_attributes.duration_doubtful {
+        let stream = media_attributes
+            .audio_stream
+            .unwrap_or(media_attributes.video_stream);
+        let scan = || media_capture.scan_duration(stream);
+        let scanned = match &cache {
+            Some(cache) => cache.duration_or_else(stream, scan),
+            None => scan(),
+        };
+        match scanned {
+            Ok(duration_seconds) => {
+                debug!(
+                    "{}: duration recovered from packets is {}",
+                    media_attributes.filename,
+                    models::MediaInfo::pretty_duration(duration_seconds, true, true)
+                );
+                media_attributes.set_duration(duration_seconds, models::DurationSource::Packets);
+            }
+            Err(err) if media_attributes.duration_seconds > 0.0 => {
+                warn!(
+                    "{}: cannot recover duration from packets, using probed duration: {}",
+                    media_attributes.filename, err
+                );
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    let media_capture = media_capture.with_duration(media_attributes.duration_seconds);
 
     if args.metadata_margin != constants::DEFAULT_METADATA_MARGIN {
         args.metadata_horizontal_margin = args.metadata_margin;
         args.metadata_vertical_margin = args.metadata_margin;
     }
 
+    let use_chapters = args.chapters && !media_attributes.chapters.is_empty();
+    if args.chapters && !use_chapters {
+        warn!(
+            "{} has no chapters, spacing captures evenly",
+            media_attributes.filename
+        );
+    }
+
     if args.interval.is_none()
         && args.manual_timestamps.is_empty()
+        && !use_chapters
         && (args.grid.x == 0 || args.grid.y == 0)
     {
         return Err(errors::VcsrError::ArgumentError(
-            "Row or column of size zero is only supported with --interval or --manual.".to_string(),
+            "Row or column of size zero is only supported with --interval, --manual or --chapters."
+                .to_string(),
         ));
     }
 
@@ -170,7 +407,16 @@ pub fn process_file(
         args.num_groups = mframes_size;
     }
 
-    if args.interval.is_some() || !args.manual_timestamps.is_empty() {
+    // chapter frame selection
+    if use_chapters {
+        let chapters_size =
+            Some(media_attributes.chapters.len() as u64 * args.captures_per_chapter);
+        args.num_samples = chapters_size;
+        args.num_selected = chapters_size;
+        args.num_groups = chapters_size;
+    }
+
+    if args.interval.is_some() || !args.manual_timestamps.is_empty() || use_chapters {
         let square_side = (args.num_samples.unwrap() as f32).sqrt().ceil() as u64;
 
         if args.grid == constants::DEFAULT_GRID_SIZE || (args.grid.x == 0 && args.grid.y == 0) {
@@ -202,7 +448,7 @@ pub fn process_file(
     }
 
     // make sure num_selected isn't too large
-    if args.interval.is_none() && args.manual_timestamps.is_empty() {
+    if args.interval.is_none() && args.manual_timestamps.is_empty() && !use_chapters {
         if args.num_selected.unwrap() > args.num_groups.unwrap() {
             args.num_groups = args.num_selected;
         }
@@ -227,9 +473,51 @@ pub fn process_file(
         args.grid_vertical_spacing = grid_spacing;
     }
 
+    // Audio-only files have their waveform and spectrogram drawn in place
+    // of captures, each cell showing the audio between its neighbours.
+    let audio_source = media_attributes.audio_stream.map(|stream| {
+        audio::AudioSource::new(
+            &media_capture,
+            stream,
+            args.audio_view,
+            operations::capture_interval(&media_attributes, args),
+        )
+    });
+    let (source, capture_key): (&dyn models::FrameSource, String) = match &audio_source {
+        Some(audio_source) => (audio_source, audio_source.cache_key()),
+        None => (&media_capture, media_capture.cache_key()),
+    };
+    let cached_capture;
+    let frame_source: &dyn models::FrameSource = match &cache {
+        Some(cache) => {
+            cached_capture = cache.frame_source(source, &capture_key);
+            &cached_capture
+        }
+        None => source,
+    };
+
+    if args.autocrop && audio_source.is_none() {
+        media_attributes.crop = operations::detect_crop(&media_attributes, frame_source, &args)?;
+        if let Some(crop) = &media_attributes.crop {
+            debug!("cropping black bars to {:?}", crop);
+        }
+    }
+    let cropped_source;
+    let frame_source: &dyn models::FrameSource = match media_attributes.crop {
+        Some(crop) => {
+            let frame = models::Grid {
+                x: media_attributes.dimensions.display_width.unwrap_or(1),
+                y: media_attributes.dimensions.display_height.unwrap_or(1),
+            };
+            cropped_source = models::CroppedSource::new(frame_source, crop, frame);
+            &cropped_source
+        }
+        None => frame_source,
+    };
+
     if args.actual_size {
         let x = args.grid.x;
-        args.vcs_width = x * media_attributes.dimensions.display_width.unwrap() as u64
+        args.vcs_width = x * media_attributes.visible_dimensions().display_width.unwrap() as u64
             + (x - 1) * args.grid_horizontal_spacing;
     }
 
@@ -240,8 +528,28 @@ pub fn process_file(
         .progress_chars("##-");
     bar.set_style(bar_style);
 
-    let (mut selected_frames, temp_frames) =
-        operations::select_sharpest_images(&media_attributes, &media_capture, &args, &bar)?;
+    let mut selected_frames =
+        operations::select_sharpest_images(&media_attributes, frame_source, &args, &bar)?;
+
+    if args.burn_subtitles {
+        let subtitle_source = models::MediaInfo::find_subtitle_source(
+            name,
+            &media_info.ffprobe,
+            args.subtitle_file.as_deref(),
+            args.subtitle_stream,
+        );
+        match subtitle_source {
+            Some(source) => {
+                bar.set_message("burning in subtitles");
+                let cues = media_capture.subtitle_cues(&source)?;
+                operations::burn_subtitles(&mut selected_frames, &cues, &args)?;
+            }
+            None => warn!(
+                "no text subtitles found for {}, not burning in subtitles",
+                file_name_str
+            ),
+        }
+    }
 
     bar.set_message("finished capturing, composing");
 
@@ -257,22 +565,17 @@ pub fn process_file(
         }
         info!("Copying thumbnails to {}", thumbnail_output_path);
         for (i, frame) in selected_frames.iter().enumerate() {
-            let thumbnail_file_extension = Path::new(&frame.filename).extension().unwrap();
             let thumbnail_filename = format!(
                 "{}.{:0>4}.{}",
-                dir_entry.path().file_stem().unwrap().to_str().unwrap(),
+                name.file_stem().unwrap().to_str().unwrap(),
                 i,
-                thumbnail_file_extension.to_str().unwrap()
+                if args.fast { "jpg" } else { "png" }
             );
             let thumbnail_destination = Path::new(thumbnail_output_path).join(thumbnail_filename);
-            std::fs::copy(&frame.filename, thumbnail_destination)?;
+            frame.image.save(thumbnail_destination)?;
         }
     }
 
-    for frame in temp_frames {
-        std::fs::remove_file(frame.filename)?;
-    }
-
     let m = format!(
         "succesfully created {}",
         output_path.file_name().unwrap().to_string_lossy()
@@ -284,6 +587,79 @@ pub fn process_file(
     Ok(output_path)
 }
 
+/// Check the ffmpeg and ffprobe executables run and that ffmpeg has every
+/// filter the requested options need.
+pub fn check_executables(args: &args::Args) -> Result<models::Capabilities, errors::VcsrError> {
+    let capabilities = args.executables().capabilities()?;
+
+    let mut required = vec![("scale", "captures")];
+    if args.frame_type.is_some() {
+        required.push(("select", "--frame-type"));
+    }
+    if args.deinterlace != models::Deinterlace::Off {
+        required.push(("yadif", "--deinterlace"));
+    }
+    if args.scenes {
+        required.push(("select", "--scenes"));
+        required.push(("showinfo", "--scenes"));
+    }
+    if args.capture_mode == models::CaptureMode::SinglePass {
+        required.push(("select", "--capture-mode single-pass"));
+        required.push(("setsar", "--capture-mode single-pass"));
+    }
+    for (filter, feature) in required {
+        capabilities.require_filter(filter, feature)?;
+    }
+    Ok(capabilities)
+}
+
 pub fn grid_from_str(s: &str) -> Result<models::Grid, errors::VcsrError> {
     models::Grid::from_str(s)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_are_named_after_the_label_or_pipe() {
+        let mut args = args::Args::default();
+        assert_eq!(stream_label(&args, None), "stdin");
+        assert_eq!(stream_label(&args, Some(Path::new("/tmp/feed"))), "feed");
+        args.label = Some(String::from("camera"));
+        assert_eq!(stream_label(&args, Some(Path::new("/tmp/feed"))), "camera");
+    }
+
+    #[test]
+    fn spools_are_removed_when_dropped() {
+        let spool = Spool::new(&mut io::Cursor::new(b"video")).unwrap();
+        let other = Spool::new(&mut io::Cursor::new(b"other")).unwrap();
+        assert_ne!(spool.path, other.path);
+        assert_eq!(fs::read(&spool.path).unwrap(), b"video");
+        let path = spool.path.clone();
+        drop(spool);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn streams_are_processed_from_a_spool() {
+        let mut args = args::Args {
+            label: Some(String::from("camera")),
+            ..Default::default()
+        };
+        let spooled = spool_stream(
+            &mut io::Cursor::new(b"video"),
+            Some(Path::new("/tmp/feed")),
+            &mut args,
+            |name, input, args| {
+                assert_eq!(name, Path::new("/tmp/camera"));
+                assert_eq!(fs::read(input).unwrap(), b"video");
+                assert!(args.no_cache);
+                Ok(input.to_path_buf())
+            },
+        )
+        .unwrap();
+        assert!(!spooled.exists());
+        assert_eq!(args.label.as_deref(), Some("camera"));
+    }
+}
@@ -2,19 +2,17 @@
 extern crate log;
 extern crate vcsr;
 
-use vcsr::{args, process_file};
+use vcsr::{
+    args, cache::Cache, errors::VcsrError, models::ImageSequence, process, process_file,
+    process_stream,
+};
 
 use indicatif::MultiProgress;
-use std::{
-    error::Error,
-    ffi::OsStr,
-    path::Path,
-    process::{Command, Stdio},
-};
+use std::{error::Error, ffi::OsStr, fs, io, path::Path};
 use walkdir::WalkDir;
 
 pub fn main() -> Result<(), Box<dyn Error>> {
-    let args = args::application_args();
+    let mut args = args::application_args();
     let level = match &args.verbose {
         true => tracing::Level::DEBUG,
         false => tracing::Level::INFO,
@@ -43,18 +41,40 @@ pub fn main() -> Result<(), Box<dyn Error>> {
 
     let multi = MultiProgress::new();
 
-    match Command::new("ffmpeg")
-        .stdin(Stdio::null())
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .output()
-    {
-        Ok(_) => info!("ffmpeg installed. Continuing."),
-        Err(_) => {
-            error!("ffmpeg not installed. Exiting.");
+    ctrlc::set_handler(|| {
+        if process::is_cancelled() {
+            process::remove_temp_files();
+            std::process::exit(exitcode::TEMPFAIL);
+        }
+        error!("Interrupted, stopping ffmpeg. Press Ctrl-C again to exit immediately.");
+        process::cancel();
+    })?;
+
+    #[cfg(not(feature = "libav"))]
+    match vcsr::check_executables(&args) {
+        Ok(capabilities) => {
+            info!("ffmpeg {} installed. Continuing.", capabilities.version);
+            if args.tonemap != vcsr::models::Tonemap::Off
+                && !(capabilities.has_filter("zscale") && capabilities.has_filter("tonemap"))
+            {
+                warn!(
+                    "ffmpeg has no zscale or tonemap filter, HDR captures will not be tone mapped"
+                );
+                args.tonemap = vcsr::models::Tonemap::Off;
+            }
+        }
+        Err(err) => {
+            error!("{}. Exiting.", err);
             std::process::exit(exitcode::SOFTWARE)
         }
     };
+    #[cfg(feature = "libav")]
+    if args.tonemap != vcsr::models::Tonemap::Off
+        && !(vcsr::has_filter("zscale") && vcsr::has_filter("tonemap"))
+    {
+        warn!("FFmpeg has no zscale or tonemap filter, HDR captures will not be tone mapped");
+        args.tonemap = vcsr::models::Tonemap::Off;
+    }
     let mut walker: WalkDir;
     rayon::ThreadPoolBuilder::new()
         .num_threads(num_cpus::get() * 2)
@@ -63,6 +83,26 @@ pub fn main() -> Result<(), Box<dyn Error>> {
 
     for path in &args.filenames {
         debug!("processing path: {}", path);
+        // stdin and named pipes cannot be walked, or seeked in by ffmpeg.
+        let is_stream = fs::metadata(path)
+            .map(|metadata| !metadata.is_file() && !metadata.is_dir())
+            .unwrap_or(false);
+        if path == "-" || is_stream {
+            let mut current_args = args.clone();
+            let result = if path == "-" {
+                process_stream(&mut io::stdin().lock(), None, &mut current_args, &multi)
+            } else {
+                fs::File::open(path)
+                    .map_err(VcsrError::from)
+                    .and_then(|mut pipe| {
+                        process_stream(&mut pipe, Some(Path::new(path)), &mut current_args, &multi)
+                    })
+            };
+            if let Err(err) = result {
+                error!("Skipped {}: {}", path, err);
+            }
+            continue;
+        }
         if !Path::new(path).exists() {
             error!("File does not exist, trying next: {}", path);
             debug!("File exists, continuing");
@@ -73,12 +113,42 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         } else {
             walker = WalkDir::new(path).max_depth(1);
         }
+        // Directories holding an image sequence are processed as one
+        // video, and are walked before the images in them, which are then
+        // skipped. Directories are only taken as a sequence when they hold
+        // no other media, so numbered stills next to videos do not hide
+        // them, and subdirectories need a longer sequence than the
+        // directory given.
+        let mut sequences: Vec<ImageSequence> = vec![];
         for entry in walker
             .into_iter()
             .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .filter(|e| e.path().extension() != None)
             .filter(|e| {
+                if e.file_type().is_dir() {
+                    match ImageSequence::find(e.path()) {
+                        Some(sequence)
+                            if (e.depth() == 0 || sequence.is_long())
+                                && !has_other_media(
+                                    e.path(),
+                                    &sequence,
+                                    &args.exclude_extensions,
+                                ) =>
+                        {
+                            sequences.push(sequence);
+                            true
+                        }
+                        _ => false,
+                    }
+                } else {
+                    e.file_type().is_file()
+                        && !sequences.iter().any(|sequence| sequence.contains(e.path()))
+                }
+            })
+            .filter(|e| e.file_type().is_dir() || e.path().extension() != None)
+            .filter(|e| {
+                if e.file_type().is_dir() {
+                    return true;
+                }
                 let extension = e.path().extension().and_then(OsStr::to_str).unwrap();
                 if args.exclude_extensions.contains(&String::from(extension)) {
                     info!("Excluded extension {}. Skipping.", extension);
@@ -88,6 +158,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
                 }
             })
         {
+            if process::is_cancelled() {
+                break;
+            }
             let mut current_args = args.clone();
             let entry_copy = entry.clone();
             match process_file(&entry, &mut current_args, &multi) {
@@ -98,6 +171,9 @@ pub fn main() -> Result<(), Box<dyn Error>> {
                     );
                     debug!("{}", &m);
                 }
+                Err(err @ VcsrError::StillImage(_)) => {
+                    info!("{}. Skipping.", err);
+                }
                 Err(err) => {
                     error!(
                         "Skipped {}: {}",
@@ -109,5 +185,35 @@ pub fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    if !args.no_cache && args.cache_size > 0 {
+        let root = args.cache_dir.as_deref().map(Path::new);
+        if let Err(err) = Cache::prune(root, args.cache_size * 1024 * 1024) {
+            warn!("cannot prune cache: {}", err);
+        }
+    }
+
+    if process::is_cancelled() {
+        std::process::exit(exitcode::TEMPFAIL);
+    }
     std::process::exit(exitcode::OK);
 }
+
+/// Whether `dir` holds files other than the images of `sequence` that
+/// would be processed, ignoring stills and excluded extensions.
+fn has_other_media(dir: &Path, sequence: &ImageSequence, exclude_extensions: &[String]) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries.filter_map(|entry| entry.ok()).any(|entry| {
+                let path = entry.path();
+                let extension = match path.extension().and_then(OsStr::to_str) {
+                    Some(extension) => extension.to_lowercase(),
+                    None => return false,
+                };
+                path.is_file()
+                    && !sequence.contains(&path)
+                    && !exclude_extensions.contains(&extension)
+                    && !ImageSequence::is_image(&path)
+            })
+        })
+        .unwrap_or(false)
+}
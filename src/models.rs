@@ -1,15 +1,19 @@
 use crate::constants::*;
 use crate::errors::VcsrError;
-use image;
+use crate::process;
+use image::{self, RgbaImage};
 use rustfft::{num_complex::Complex, FftDirection, FftPlanner};
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 use std::{
+    collections::BTreeMap,
     error::Error,
-    fmt, io,
-    path::Path,
-    process::{Command, Stdio},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Output},
     str,
     str::FromStr,
+    time::{Duration, UNIX_EPOCH},
 };
 
 #[derive(Clone, Debug, Default)]
@@ -53,7 +57,7 @@ impl FromStr for Grid {
 pub struct Frame {
     pub avg_colour: f32,
     pub blurriness: f32,
-    pub filename: String,
+    pub image: RgbaImage,
     pub timestamp: f32,
 }
 
@@ -65,17 +69,240 @@ pub struct MediaInfo {
 
 #[derive(Clone, Debug, Default)]
 pub struct MediaAttributes {
+    /// Whether the video is an animated image, such as a GIF, whose
+    /// captures are labelled with the index of the frame shown.
+    pub animated: bool,
+    /// Index of the audio stream drawn in place of captures when the file
+    /// has no video, see `MediaInfo::create_audio_attributes`.
+    pub audio_stream: Option<u32>,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub chapters: Vec<ChapterMarker>,
+    /// Part of the picture left once black bars are cropped, in display
+    /// pixels, see `operations::detect_crop`.
+    pub crop: Option<Crop>,
     pub dimensions: Dimensions,
     pub display_aspect_ratio: Option<String>,
     pub duration: String,
     pub duration_seconds: f32,
+    /// Where `duration_seconds` was read from, or `None` when the probe
+    /// has no duration, to be recovered with `MediaCapture::scan_duration`.
+    pub duration_source: Option<DurationSource>,
+    /// Whether the probed duration disagrees with the container's, and is
+    /// to be checked against the packets too.
+    pub duration_doubtful: bool,
     pub filename: String,
-    pub frame_rate: u32,
+    /// Whether the video uses an HDR transfer, see `MediaInfo::is_hdr`.
+    pub hdr: bool,
+    /// The numbered images read as the video, if it is an image sequence.
+    pub image_sequence: Option<ImageSequence>,
+    /// Whether the video is interlaced, see `MediaInfo::is_interlaced`.
+    pub interlaced: bool,
+    /// The average frame rate, or the base frame rate when the average is
+    /// unknown.
+    pub frame_rate: Option<FrameRate>,
+    pub avg_frame_rate: Option<FrameRate>,
+    pub r_frame_rate: Option<FrameRate>,
+    /// Whether frames are not evenly spaced, which ffprobe shows as an
+    /// average frame rate differing from the base frame rate.
+    pub variable_frame_rate: bool,
+    /// Clockwise rotation in degrees applied to captures, see
+    /// `MediaInfo::compute_rotation`.
+    pub rotation: u32,
     pub sample_aspect_ratio: Option<String>,
     pub size_bytes: f64,
     pub size: String,
+    /// Presentation time in seconds the file starts at, which is often not
+    /// zero for MPEG-TS. Capture times are counted from it. Kept in f64,
+    /// as MPEG-TS start times reach 95000 s, past the precision of f32.
+    pub start_time: f64,
+    pub subtitle_tracks: Vec<SubtitleTrack>,
     pub video_codec: Option<String>,
     pub video_codec_long: Option<String>,
+    /// Index of the stream captures are taken from.
+    pub video_stream: u32,
+}
+
+/// The details of an audio stream shown in the header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AudioTrack {
+    pub index: u32,
+    pub codec: Option<String>,
+    pub channels: Option<u32>,
+    pub channel_layout: Option<String>,
+    pub sample_rate: Option<u32>,
+    pub bit_rate: Option<u64>,
+    pub language: Option<String>,
+}
+
+impl fmt::Display for AudioTrack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut details = vec![];
+        details.extend(self.codec.clone());
+        match (&self.channel_layout, self.channels) {
+            (Some(layout), Some(channels)) => {
+                details.push(format!("{} ({} channels)", layout, channels))
+            }
+            (Some(layout), None) => details.push(layout.clone()),
+            (None, Some(channels)) => details.push(format!("{} channels", channels)),
+            (None, None) => {}
+        }
+        details.extend(self.sample_rate.map(|rate| format!("{} Hz", rate)));
+        details.extend(self.bit_rate.map(|rate| format!("{} kb/s", rate / 1000)));
+        details.extend(self.language.clone());
+        write!(f, "Audio #{}: {}", self.index, details.join(", "))
+    }
+}
+
+/// The details of a subtitle stream shown in the header.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubtitleTrack {
+    pub index: u32,
+    pub codec: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub forced: bool,
+}
+
+impl fmt::Display for SubtitleTrack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut details = vec![];
+        details.extend(self.codec.clone());
+        details.extend(self.language.clone());
+        details.extend(self.title.clone());
+        if self.forced {
+            details.push(String::from("forced"));
+        }
+        write!(f, "Subtitles #{}: {}", self.index, details.join(", "))
+    }
+}
+
+/// A subtitle cue, with its bounds in seconds and its text without any
+/// styling.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubtitleCue {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+/// Where subtitles to burn in are read from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubtitleSource {
+    /// A subtitle file, such as a .srt next to the video.
+    File(PathBuf),
+    /// The stream with this index in the video.
+    Stream(u32),
+}
+
+/// A chapter's bounds in seconds and its title.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChapterMarker {
+    pub start: f32,
+    pub end: f32,
+    pub title: Option<String>,
+}
+
+/// An exact frame rate, such as 24000/1001 for 23.976 fps.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRate {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl FrameRate {
+    pub fn fps(&self) -> f64 {
+        f64::from(self.numerator) / f64::from(self.denominator)
+    }
+
+    /// Whether two rates are within 0.5% of each other, which absorbs the
+    /// rounding some muxers apply to average frame rates.
+    pub fn approx_eq(&self, other: &FrameRate) -> bool {
+        (self.fps() - other.fps()).abs() <= other.fps() * 0.005
+    }
+
+    /// Whether frames are not evenly spaced, which ffprobe shows as an
+    /// `avg` frame rate differing from the `base` frame rate. Interlaced
+    /// video can have a base rate of twice the average, counting fields,
+    /// so only rates that are not a whole multiple of each other count.
+    pub fn is_variable(avg: &FrameRate, base: &FrameRate) -> bool {
+        if avg.fps() <= 0.0 {
+            return false;
+        }
+        let ratio = base.fps() / avg.fps();
+        ratio < 0.995 || (ratio - ratio.round()).abs() > ratio.round() * 0.005
+    }
+
+    /// Number of the frame shown at `seconds`, counting from zero.
+    pub fn frame_number(&self, seconds: f32) -> u64 {
+        let frames = f64::from(seconds) * f64::from(self.numerator) / f64::from(self.denominator);
+        // Allow for the rounding of f32 timestamps so the time a frame
+        // starts at maps to that frame.
+        (frames + 1e-3).floor().max(0.0) as u64
+    }
+
+    /// SMPTE timecode of the frame shown at `seconds`. NTSC rates such as
+    /// 30000/1001 use drop-frame timecode, separating frames with `;`.
+    pub fn timecode(&self, seconds: f32) -> String {
+        let nominal = self.fps().round().max(1.0) as u64;
+        let drop_frame = self.denominator == 1001 && nominal % 30 == 0;
+        let mut frames = self.frame_number(seconds);
+        if drop_frame {
+            // Skip the frame numbers dropped at the start of every minute
+            // except each tenth.
+            let dropped = nominal / 15;
+            let per_ten_minutes = nominal * 600 - dropped * 9;
+            let per_minute = nominal * 60 - dropped;
+            let ten_minutes = frames / per_ten_minutes;
+            let remainder = frames % per_ten_minutes;
+            frames += dropped * 9 * ten_minutes;
+            if remainder > dropped {
+                frames += dropped * ((remainder - dropped) / per_minute);
+            }
+        }
+        format!(
+            "{:02}:{:02}:{:02}{}{:02}",
+            frames / (nominal * 3600),
+            frames / (nominal * 60) % 60,
+            frames / nominal % 60,
+            if drop_frame { ';' } else { ':' },
+            frames % nominal
+        )
+    }
+}
+
+impl fmt::Display for FrameRate {
+    /// Whole rates as is and others to three decimals, as in 23.976.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.numerator % self.denominator == 0 {
+            write!(f, "{}", self.numerator / self.denominator)
+        } else {
+            let fps = format!("{:.3}", self.fps());
+            write!(f, "{}", fps.trim_end_matches('0'))
+        }
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = VcsrError;
+
+    /// Parse ffprobe's `num/den` form, or a plain whole number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || VcsrError::FrameRateError(s.to_string());
+        let (numerator, denominator) = match s.split_once('/') {
+            Some((numerator, denominator)) => (
+                numerator.trim().parse::<u32>().map_err(|_| invalid())?,
+                denominator.trim().parse::<u32>().map_err(|_| invalid())?,
+            ),
+            None => (s.trim().parse::<u32>().map_err(|_| invalid())?, 1),
+        };
+        if numerator == 0 || denominator == 0 {
+            return Err(invalid());
+        }
+        Ok(FrameRate {
+            numerator,
+            denominator,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -86,31 +313,199 @@ pub struct Dimensions {
     pub sample_width: Option<u64>,
 }
 
+impl MediaAttributes {
+    /// Dimensions of the picture shown in each cell, which is smaller than
+    /// the video when black bars are cropped.
+    pub fn visible_dimensions(&self) -> Dimensions {
+        match &self.crop {
+            Some(crop) => Dimensions {
+                display_width: Some(crop.width),
+                display_height: Some(crop.height),
+                ..self.dimensions.clone()
+            },
+            None => self.dimensions.clone(),
+        }
+    }
+
+    pub fn set_duration(&mut self, duration_seconds: f32, source: DurationSource) {
+        self.duration = MediaInfo::pretty_duration(duration_seconds, true, true);
+        self.duration_seconds = duration_seconds;
+        self.duration_source = Some(source);
+        self.duration_doubtful = false;
+    }
+}
+
+/// Where the duration of a file was read from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DurationSource {
+    /// The duration of the captured stream.
+    Stream,
+    /// The duration of the container, for streams without their own.
+    Container,
+    /// The span of the stream's packet timestamps, for files whose probed
+    /// duration is missing or doubtful, such as truncated recordings.
+    Packets,
+}
+
+/// A rectangle of a frame, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crop {
+    pub x: u64,
+    pub y: u64,
+    pub width: u64,
+    pub height: u64,
+}
+
+impl Crop {
+    /// The part of `image` left after trimming the rows and columns along
+    /// its edges whose mean luma is at most `limit`, or `None` if the whole
+    /// image is that dark.
+    pub fn of_image(image: &RgbaImage, limit: u8) -> Option<Crop> {
+        let luma = |x: u32, y: u32| {
+            let pixel = image.get_pixel(x, y);
+            (299 * u32::from(pixel[0]) + 587 * u32::from(pixel[1]) + 114 * u32::from(pixel[2]))
+                / 1000
+        };
+        let (width, height) = image.dimensions();
+        let row_is_bar =
+            |y: u32| (0..width).map(|x| luma(x, y)).sum::<u32>() <= u32::from(limit) * width;
+        let top = (0..height).find(|y| !row_is_bar(*y))?;
+        let bottom = (0..height).rev().find(|y| !row_is_bar(*y))?;
+
+        // Columns are only measured between the letterbox bars, so bars
+        // on both axes are found.
+        let rows = bottom - top + 1;
+        let column_is_bar =
+            |x: u32| (top..=bottom).map(|y| luma(x, y)).sum::<u32>() <= u32::from(limit) * rows;
+        let left = (0..width).find(|x| !column_is_bar(*x))?;
+        let right = (0..width).rev().find(|x| !column_is_bar(*x))?;
+
+        Some(Crop {
+            x: u64::from(left),
+            y: u64::from(top),
+            width: u64::from(right - left + 1),
+            height: u64::from(rows),
+        })
+    }
+
+    /// The smallest rectangle holding both `self` and `other`.
+    pub fn union(&self, other: &Crop) -> Crop {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Crop {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+
+    /// This rectangle of a `from` sized frame, moved onto the same frame
+    /// scaled to `to`.
+    pub fn scale(&self, from: &Grid, to: &Grid) -> Crop {
+        let scale = |value: u64, from: u64, to: u64| value * to / from.max(1);
+        let x = scale(self.x, from.x, to.x);
+        let y = scale(self.y, from.y, to.y);
+        Crop {
+            x,
+            y,
+            width: scale(self.x + self.width, from.x, to.x) - x,
+            height: scale(self.y + self.height, from.y, to.y) - y,
+        }
+    }
+}
+
 impl MediaInfo {
-    pub fn new(path: &Path, _verbose: bool) -> Result<MediaInfo, VcsrError> {
-        let ffprobe = Self::probe_media(path)?;
-        let media_attributes = Self::create_media_attributes(&ffprobe)?;
+    pub fn new(
+        path: &Path,
+        _verbose: bool,
+        video_stream: Option<u32>,
+        executables: &Executables,
+    ) -> Result<MediaInfo, VcsrError> {
+        let json = Self::probe_json(path, &[], executables)?;
+        Self::from_json(&json, video_stream)
+    }
+
+    /// Build media info from ffprobe's JSON output, such as a cached probe.
+    pub fn from_json(json: &str, video_stream: Option<u32>) -> Result<MediaInfo, VcsrError> {
+        let ffprobe: Ffprobe = serde_json::from_str(json)?;
+        let media_attributes = Self::create_media_attributes(&ffprobe, video_stream)?;
         Ok(MediaInfo {
             ffprobe: ffprobe,
             media_attributes: Some(media_attributes),
         })
     }
 
-    pub fn probe_media(path: &Path) -> Result<Ffprobe, VcsrError> {
-        if path.exists() {
-            let output = Command::new("ffprobe")
-                .arg("-v")
-                .arg("quiet")
-                .arg("-print_format")
-                .arg("json")
-                .arg("-show_format")
-                .arg("-show_streams")
-                .arg(path)
-                .output()?;
-            if let Ok(stdout) = str::from_utf8(&output.stdout) {
-                let f: Ffprobe =
-                    serde_json::from_str(stdout).map_err(|e| VcsrError::StreamError(e))?;
-                Ok(f)
+    /// Build media info for `sequence` from ffprobe's JSON output. ffprobe
+    /// cannot size an image sequence, so the size of its images is used.
+    pub fn from_sequence_json(
+        json: &str,
+        sequence: &ImageSequence,
+        video_stream: Option<u32>,
+    ) -> Result<MediaInfo, VcsrError> {
+        let mut ffprobe: Ffprobe = serde_json::from_str(json)?;
+        if ffprobe.format.size.is_none() {
+            ffprobe.format.size = Some(sequence.size_bytes.to_string());
+        }
+        let mut media_attributes = Self::create_media_attributes(&ffprobe, video_stream)?;
+        media_attributes.image_sequence = Some(sequence.clone());
+        Ok(MediaInfo {
+            ffprobe,
+            media_attributes: Some(media_attributes),
+        })
+    }
+
+    pub fn probe_media(path: &Path, executables: &Executables) -> Result<Ffprobe, VcsrError> {
+        let json = Self::probe_json(path, &[], executables)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Probe `path` with the FFmpeg libraries, opened with the demuxer
+    /// `input_options`, returning JSON shaped like ffprobe's output.
+    #[cfg(feature = "libav")]
+    pub fn probe_json(
+        path: &Path,
+        input_options: &[(String, String)],
+        _executables: &Executables,
+    ) -> Result<String, VcsrError> {
+        // An image sequence pattern does not name a file itself.
+        if path.exists() || !input_options.is_empty() {
+            crate::native::probe_json(path, input_options)
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "cannot find requested video file").into())
+        }
+    }
+
+    /// Probe `path` with ffprobe, opened with the demuxer `input_options`,
+    /// returning its JSON output.
+    #[cfg(not(feature = "libav"))]
+    pub fn probe_json(
+        path: &Path,
+        input_options: &[(String, String)],
+        executables: &Executables,
+    ) -> Result<String, VcsrError> {
+        // An image sequence pattern does not name a file itself.
+        if path.exists() || !input_options.is_empty() {
+            let output = executables.run(
+                executables
+                    .ffprobe()
+                    .arg("-v")
+                    .arg("quiet")
+                    .arg("-print_format")
+                    .arg("json")
+                    .arg("-show_format")
+                    .arg("-show_streams")
+                    .arg("-show_chapters")
+                    .args(option_args(input_options))
+                    .arg(path),
+            )?;
+            if !output.status.success() {
+                Err(
+                    io::Error::new(io::ErrorKind::InvalidData, "ffprobe cannot read the file")
+                        .into(),
+                )
+            } else if let Ok(stdout) = String::from_utf8(output.stdout) {
+                Ok(stdout)
             } else {
                 Err(io::Error::new(io::ErrorKind::Other, "ffprobe crashed unexpectedly").into())
             }
@@ -132,15 +527,82 @@ impl MediaInfo {
         size
     }
 
-    pub fn find_video_stream(ffprobe: &Ffprobe) -> Option<&Stream> {
-        ffprobe.streams.iter().find(|stream| match stream {
-            Stream::VideoStream(_) => true,
-            _ => false,
+    /// The video stream with index `video_stream`, or else the first video
+    /// stream that is not an attached picture such as cover art. Files
+    /// whose only video is an attached picture use that picture.
+    pub fn find_video_stream(ffprobe: &Ffprobe, video_stream: Option<u32>) -> Option<&Stream> {
+        let mut video_streams = ffprobe
+            .streams
+            .iter()
+            .filter(|stream| matches!(stream, Stream::VideoStream(_)));
+        match video_stream {
+            Some(index) => video_streams.find(|stream| stream.stream().index == Some(index)),
+            None => video_streams
+                .clone()
+                .find(|stream| stream.stream().disposition.attached_pic == 0)
+                .or_else(|| video_streams.next()),
+        }
+    }
+
+    /// The first audio stream of a file without video, not counting cover
+    /// art.
+    pub fn find_audio_only_stream(ffprobe: &Ffprobe) -> Option<&StreamStruct> {
+        let has_video = ffprobe.streams.iter().any(|stream| {
+            matches!(stream, Stream::VideoStream(video) if video.disposition.attached_pic == 0)
+        });
+        if has_video {
+            return None;
+        }
+        ffprobe.streams.iter().find_map(|stream| match stream {
+            Stream::AudioStream(audio) => Some(audio),
+            _ => None,
+        })
+    }
+
+    /// Attributes of an audio-only file, whose cells are drawn from
+    /// `audio_stream` at `AUDIO_CELL_WIDTH` x `AUDIO_CELL_HEIGHT`.
+    pub fn create_audio_attributes(
+        ffprobe: &Ffprobe,
+        audio_stream: &StreamStruct,
+    ) -> Result<MediaAttributes, VcsrError> {
+        // Raw MP3 and ADTS streams may have no duration, which is then
+        // recovered from the packets.
+        let (duration_seconds, duration_source) = match Self::stream_duration(ffprobe, audio_stream)
+        {
+            Some((duration_seconds, source)) => (duration_seconds, Some(source)),
+            None => (0.0, None),
+        };
+        let (size_bytes, size) = Self::compute_size(ffprobe).map_err(|_| VcsrError::MediaError)?;
+        let (audio_tracks, subtitle_tracks) = Self::compute_tracks(ffprobe);
+        Ok(MediaAttributes {
+            audio_stream: Some(audio_stream.index.unwrap_or_default()),
+            audio_tracks,
+            chapters: Self::compute_chapters(ffprobe),
+            dimensions: Dimensions {
+                display_width: Some(AUDIO_CELL_WIDTH),
+                display_height: Some(AUDIO_CELL_HEIGHT),
+                sample_width: Some(AUDIO_CELL_WIDTH),
+                sample_height: Some(AUDIO_CELL_HEIGHT),
+            },
+            duration: MediaInfo::pretty_duration(duration_seconds, true, true),
+            duration_seconds,
+            duration_source,
+            filename: Self::compute_filename(ffprobe),
+            size,
+            size_bytes,
+            start_time: Self::compute_start_time(ffprobe),
+            subtitle_tracks,
+            ..Default::default()
         })
     }
 
-    pub fn compute_display_resolution(ffprobe: &Ffprobe) -> Result<Dimensions, VcsrError> {
-        let video_stream = Self::find_video_stream(ffprobe).unwrap().clone();
+    pub fn compute_display_resolution(
+        ffprobe: &Ffprobe,
+        video_stream: Option<u32>,
+    ) -> Result<Dimensions, VcsrError> {
+        let video_stream = Self::find_video_stream(ffprobe, video_stream)
+            .ok_or(VcsrError::VideoStreamError)?
+            .clone();
         if let Stream::VideoStream(video_stream) = video_stream {
             let mut display_height: Option<u64>;
             let mut display_width: Option<u64>;
@@ -148,11 +610,11 @@ impl MediaInfo {
             let mut sample_width: Option<u64>;
             sample_width = video_stream.width;
             sample_height = video_stream.height;
-            if let Some(rotation) = video_stream.tags.rotate {
-                // Swap width and height
-                if rotation == 90 {
-                    std::mem::swap(&mut sample_width, &mut sample_height);
-                }
+            // Swap width and height for streams displayed a quarter turn
+            // round, so the pixel aspect ratio then stretches the height.
+            let quarter_turn = Self::compute_rotation(&video_stream) % 180 == 90;
+            if quarter_turn {
+                std::mem::swap(&mut sample_width, &mut sample_height);
             }
 
             let sample_aspect_ratio = video_stream
@@ -176,9 +638,14 @@ impl MediaInfo {
                     .parse::<u64>()
                     .unwrap();
 
-                let new_sample_width = sample_width.unwrap() * sw / sh;
-                display_width = Some(new_sample_width);
-                display_height = sample_height;
+                if quarter_turn {
+                    display_width = sample_width;
+                    display_height = Some(sample_height.unwrap() * sw / sh);
+                } else {
+                    let new_sample_width = sample_width.unwrap() * sw / sh;
+                    display_width = Some(new_sample_width);
+                    display_height = sample_height;
+                }
             }
 
             if let Some(option_display_width) = display_width {
@@ -201,19 +668,249 @@ impl MediaInfo {
         Err(VcsrError::VideoStreamError)
     }
 
-    pub fn compute_duration(ffprobe: &Ffprobe) -> Option<(f32, String)> {
-        let video_stream = Self::find_video_stream(ffprobe).unwrap();
-        if let Stream::VideoStream(video_stream) = video_stream {
-            let duration = match &video_stream.duration {
-                Some(duration) => duration,
-                None => &ffprobe.format.duration,
+    /// Whether `stream` is HDR10 or HLG video, going by its transfer
+    /// characteristics.
+    pub fn is_hdr(stream: &StreamStruct) -> bool {
+        stream
+            .color_transfer
+            .as_deref()
+            .is_some_and(|transfer| HDR_TRANSFERS.contains(&transfer))
+    }
+
+    /// Whether `stream` is interlaced, going by its field order. Streams of
+    /// unknown field order are taken to be progressive.
+    pub fn is_interlaced(stream: &StreamStruct) -> bool {
+        stream
+            .field_order
+            .as_deref()
+            .is_some_and(|field_order| INTERLACED_FIELD_ORDERS.contains(&field_order))
+    }
+
+    /// Clockwise rotation needed to display `stream` upright, as one of 0,
+    /// 90, 180 or 270 degrees. A display matrix in the side data takes
+    /// precedence over the legacy `rotate` tag. ffprobe reports the matrix
+    /// rotation counter-clockwise while the tag is clockwise.
+    pub fn compute_rotation(stream: &StreamStruct) -> u32 {
+        let rotation = stream
+            .side_data_list
+            .iter()
+            .find_map(|side_data| side_data.rotation)
+            .map(|rotation| -rotation)
+            .or(stream.tags.rotate.map(|rotate| rotate as f32))
+            .unwrap_or(0.0);
+        ((rotation / 90.0).round() as i32 * 90).rem_euclid(360) as u32
+    }
+
+    /// The duration of the video stream, or of the container when the
+    /// stream has none, and which of them it is.
+    pub fn compute_duration(
+        ffprobe: &Ffprobe,
+        video_stream: Option<u32>,
+    ) -> Option<(f32, DurationSource)> {
+        Self::stream_duration(
+            ffprobe,
+            Self::find_video_stream(ffprobe, video_stream)?.stream(),
+        )
+    }
+
+    fn stream_duration(ffprobe: &Ffprobe, stream: &StreamStruct) -> Option<(f32, DurationSource)> {
+        let parse = |duration: &Option<String>| {
+            duration
+                .as_ref()?
+                .parse::<f32>()
+                .ok()
+                .filter(|duration| duration.is_finite() && *duration > 0.0)
+        };
+        parse(&stream.duration)
+            .map(|duration| (duration, DurationSource::Stream))
+            .or_else(|| {
+                parse(&ffprobe.format.duration)
+                    .map(|duration| (duration, DurationSource::Container))
+            })
+    }
+
+    /// The container's start time, or zero when it is missing.
+    pub fn compute_start_time(ffprobe: &Ffprobe) -> f64 {
+        ffprobe
+            .format
+            .start_time
+            .as_ref()
+            .and_then(|start_time| start_time.parse::<f64>().ok())
+            .filter(|start_time| start_time.is_finite())
+            .unwrap_or(0.0)
+    }
+
+    /// The container and video stream durations, when they differ by more
+    /// than `DURATION_MISMATCH_PERCENT`, which happens with truncated
+    /// recordings and streams whose container duration is estimated from
+    /// the bit rate.
+    pub fn duration_mismatch(ffprobe: &Ffprobe, video_stream: Option<u32>) -> Option<(f32, f32)> {
+        let stream = Self::find_video_stream(ffprobe, video_stream)?.stream();
+        let container = ffprobe.format.duration.as_ref()?.parse::<f32>().ok()?;
+        let stream = stream.duration.as_ref()?.parse::<f32>().ok()?;
+        let difference = (container - stream).abs() * 100.0 / container.max(stream);
+        if difference > DURATION_MISMATCH_PERCENT {
+            Some((container, stream))
+        } else {
+            None
+        }
+    }
+
+    /// Chapters in order of their start, skipping any without a usable
+    /// start or end.
+    pub fn compute_chapters(ffprobe: &Ffprobe) -> Vec<ChapterMarker> {
+        let mut chapters: Vec<ChapterMarker> = ffprobe
+            .chapters
+            .iter()
+            .filter_map(|chapter| {
+                let start = chapter.start_time.as_ref()?.parse::<f32>().ok()?;
+                let end = chapter.end_time.as_ref()?.parse::<f32>().ok()?;
+                if end <= start {
+                    return None;
+                }
+                Some(ChapterMarker {
+                    start,
+                    end,
+                    title: chapter.tags.title.clone(),
+                })
+            })
+            .collect();
+        chapters.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+        chapters
+    }
+
+    /// Audio and subtitle streams, in stream order.
+    pub fn compute_tracks(ffprobe: &Ffprobe) -> (Vec<AudioTrack>, Vec<SubtitleTrack>) {
+        let mut audio_tracks = vec![];
+        let mut subtitle_tracks = vec![];
+        for stream in &ffprobe.streams {
+            match stream {
+                Stream::AudioStream(audio) => audio_tracks.push(AudioTrack {
+                    index: audio.index.unwrap_or_default(),
+                    codec: audio.codec_name.clone(),
+                    channels: audio.channels,
+                    channel_layout: audio.channel_layout.clone(),
+                    sample_rate: audio
+                        .sample_rate
+                        .as_ref()
+                        .and_then(|rate| rate.parse().ok()),
+                    bit_rate: Self::compute_bit_rate(audio),
+                    language: audio.tags.language.clone(),
+                }),
+                Stream::SubtitleStream(subtitle) => subtitle_tracks.push(SubtitleTrack {
+                    index: subtitle.index.unwrap_or_default(),
+                    codec: subtitle.codec_name.clone(),
+                    language: subtitle.tags.language.clone(),
+                    title: subtitle.tags.title.clone(),
+                    forced: subtitle.disposition.forced == 1,
+                }),
+                _ => {}
+            }
+        }
+        (audio_tracks, subtitle_tracks)
+    }
+
+    /// A stream's bit rate, falling back to the `BPS` statistics tag
+    /// Matroska muxers write for streams without one.
+    fn compute_bit_rate(stream: &StreamStruct) -> Option<u64> {
+        stream
+            .bit_rate
+            .as_ref()
+            .and_then(|rate| rate.parse().ok())
+            .or_else(|| {
+                ["BPS", "BPS-eng"]
+                    .iter()
+                    .filter_map(|tag| stream.tags.extra.get(*tag)?.as_str()?.parse().ok())
+                    .next()
+            })
+    }
+
+    /// The subtitles to burn into captures of `path`: `subtitle_file` or
+    /// `subtitle_stream` when given, else a subtitle file named after the
+    /// video, else the first embedded text subtitle stream. Bitmap
+    /// subtitle streams, such as PGS and DVD subtitles, cannot be drawn and
+    /// are never returned.
+    pub fn find_subtitle_source(
+        path: &Path,
+        ffprobe: &Ffprobe,
+        subtitle_file: Option<&str>,
+        subtitle_stream: Option<u32>,
+    ) -> Option<SubtitleSource> {
+        if let Some(subtitle_file) = subtitle_file {
+            return Some(SubtitleSource::File(PathBuf::from(subtitle_file)));
+        }
+        let text_streams = || {
+            ffprobe
+                .streams
+                .iter()
+                .filter_map(|stream| match stream {
+                    Stream::SubtitleStream(subtitle) => Some(subtitle),
+                    _ => None,
+                })
+                .filter(|subtitle| {
+                    subtitle
+                        .codec_name
+                        .as_deref()
+                        .is_some_and(|codec| TEXT_SUBTITLE_CODECS.contains(&codec))
+                })
+        };
+        if let Some(index) = subtitle_stream {
+            if text_streams().any(|subtitle| subtitle.index == Some(index)) {
+                return Some(SubtitleSource::Stream(index));
             }
-            .to_string();
-            let duration_seconds = duration.parse::<f32>().unwrap();
-            let duration = MediaInfo::pretty_duration(duration.parse::<f32>().unwrap(), true, true);
-            return Some((duration_seconds, duration));
+            warn!("stream {} is not a text subtitle stream", index);
+            return None;
+        }
+        Self::find_subtitle_file(path)
+            .map(SubtitleSource::File)
+            .or_else(|| {
+                text_streams()
+                    .find_map(|subtitle| subtitle.index)
+                    .map(SubtitleSource::Stream)
+            })
+    }
+
+    /// A subtitle file next to `path` named after it, such as `movie.srt`,
+    /// or else with a language or other tag, such as `movie.en.srt`.
+    fn find_subtitle_file(path: &Path) -> Option<PathBuf> {
+        let exact = SUBTITLE_EXTENSIONS
+            .iter()
+            .map(|extension| path.with_extension(extension))
+            .find(|sidecar| sidecar.is_file());
+        if exact.is_some() {
+            return exact;
         }
-        None
+        let prefix = format!("{}.", path.file_stem()?.to_str()?);
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut tagged: Vec<PathBuf> = fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|sidecar| {
+                let tagged_name = sidecar
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with(&prefix));
+                let extension = sidecar
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| SUBTITLE_EXTENSIONS.contains(&extension));
+                tagged_name && extension && sidecar.is_file()
+            })
+            .collect();
+        tagged.sort();
+        tagged.into_iter().next()
+    }
+
+    /// The chapter `timestamp` falls in.
+    pub fn chapter_at(chapters: &[ChapterMarker], timestamp: f32) -> Option<&ChapterMarker> {
+        chapters
+            .iter()
+            .rev()
+            .find(|chapter| chapter.start <= timestamp && timestamp < chapter.end)
     }
 
     // Compute duration, size and retrieve filename
@@ -226,7 +923,12 @@ impl MediaInfo {
     }
 
     pub fn compute_size(ffprobe: &Ffprobe) -> Result<(f64, String), Box<dyn Error>> {
-        let size_bytes = ffprobe.format.size.parse::<f64>()?;
+        let size_bytes = ffprobe
+            .format
+            .size
+            .as_ref()
+            .ok_or(VcsrError::MediaError)?
+            .parse::<f64>()?;
         let size = MediaInfo::human_readable_size(size_bytes);
         Ok((size_bytes, size))
     }
@@ -266,6 +968,18 @@ impl MediaInfo {
         duration
     }
 
+    /// Like `pretty_duration`, for times such as presentation timestamps
+    /// that may be too large for f32 to hold to the millisecond.
+    pub fn pretty_time(seconds: f64, show_millis: bool) -> String {
+        let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+        let whole = MediaInfo::pretty_duration((millis / 1000) as f32, false, false);
+        if show_millis {
+            format!("{}.{:0>3}", whole, millis % 1000)
+        } else {
+            format!("{}.{:0>2}", whole, millis % 1000 / 10)
+        }
+    }
+
     pub fn pretty_to_seconds(pretty_duration: &str) -> Result<f32, VcsrError> {
         let millis_split: Vec<&str> = pretty_duration.split(".").collect();
         let mut millis = 0.0;
@@ -330,58 +1044,308 @@ impl MediaInfo {
     }
 
     // Parse multiple media attributes
-    pub fn create_media_attributes(ffprobe: &Ffprobe) -> Result<MediaAttributes, VcsrError> {
-        let dimensions = Self::compute_display_resolution(&ffprobe)?;
-        let (duration_seconds, duration) = Self::compute_duration(&ffprobe).unwrap();
+    pub fn create_media_attributes(
+        ffprobe: &Ffprobe,
+        video_stream: Option<u32>,
+    ) -> Result<MediaAttributes, VcsrError> {
+        if let Some(index) = video_stream {
+            if Self::find_video_stream(ffprobe, video_stream).is_none() {
+                return Err(VcsrError::ArgumentError(format!(
+                    "stream {} is not a video stream",
+                    index
+                )));
+            }
+        } else if let Some(audio_stream) = Self::find_audio_only_stream(ffprobe) {
+            return Self::create_audio_attributes(ffprobe, audio_stream);
+        }
+        let dimensions = Self::compute_display_resolution(&ffprobe, video_stream)?;
         let filename = Self::compute_filename(&ffprobe);
-        let (size_bytes, size) = Self::compute_size(&ffprobe).unwrap();
+        let (duration_seconds, duration_source) =
+            match Self::compute_duration(ffprobe, video_stream) {
+                Some((duration_seconds, source)) => (duration_seconds, Some(source)),
+                // Single images are read by the image2pipe demuxers, such as
+                // png_pipe, and have no duration.
+                None if ffprobe
+                    .format
+                    .format_name
+                    .as_deref()
+                    .is_some_and(|name| name.ends_with("_pipe")) =>
+                {
+                    return Err(VcsrError::StillImage(filename));
+                }
+                None => (0.0, None),
+            };
+        let duration_doubtful = match Self::duration_mismatch(ffprobe, video_stream) {
+            Some((container, stream)) => {
+                warn!(
+                    "{}: container duration {} disagrees with video stream duration {}",
+                    filename,
+                    MediaInfo::pretty_duration(container, true, true),
+                    MediaInfo::pretty_duration(stream, true, true)
+                );
+                true
+            }
+            None => false,
+        };
+        let duration = MediaInfo::pretty_duration(duration_seconds, true, true);
+        let (size_bytes, size) = Self::compute_size(&ffprobe).map_err(|_| VcsrError::MediaError)?;
         let mut video_codec = None;
         let mut video_codec_long = None;
         let mut sample_aspect_ratio = None;
         let mut display_aspect_ratio = None;
-        let mut frame_rate = 0;
+        let mut avg_frame_rate = None;
+        let mut r_frame_rate = None;
+        let mut rotation = 0;
+        let mut hdr = false;
+        let mut interlaced = false;
 
         // video
-        let video_stream = Self::find_video_stream(&ffprobe).unwrap().clone();
+        let video_stream = Self::find_video_stream(&ffprobe, video_stream)
+            .ok_or(VcsrError::VideoStreamError)?
+            .clone();
+        let video_stream_index = video_stream.stream().index.unwrap_or(0);
         if let Stream::VideoStream(video_stream) = video_stream {
+            rotation = Self::compute_rotation(&video_stream);
+            hdr = Self::is_hdr(&video_stream);
+            interlaced = Self::is_interlaced(&video_stream);
             video_codec = video_stream.codec_name;
             video_codec_long = video_stream.codec_long_name;
             sample_aspect_ratio = video_stream.sample_aspect_ratio;
             display_aspect_ratio = video_stream.display_aspect_ratio;
-            if let Some(avg_frame_rate) = video_stream.avg_frame_rate {
-                let splits: Vec<&str> = avg_frame_rate.split("/").collect();
-                if splits.len() == 2 {
-                    frame_rate =
-                        (splits[0]).parse::<u32>().unwrap() / splits[1].parse::<u32>().unwrap();
-                } else {
-                    frame_rate = avg_frame_rate.parse::<u32>().unwrap();
-                }
-
-                frame_rate = frame_rate;
-            }
+            // ffprobe reports 0/0 when a rate is unknown.
+            avg_frame_rate = video_stream
+                .avg_frame_rate
+                .and_then(|rate| rate.parse::<FrameRate>().ok());
+            r_frame_rate = video_stream
+                .r_frame_rate
+                .and_then(|rate| rate.parse::<FrameRate>().ok());
         }
+        let variable_frame_rate = match (avg_frame_rate, r_frame_rate) {
+            (Some(avg), Some(r)) => FrameRate::is_variable(&avg, &r),
+            _ => false,
+        };
 
+        let (audio_tracks, subtitle_tracks) = Self::compute_tracks(ffprobe);
         Ok(MediaAttributes {
+            animated: ffprobe
+                .format
+                .format_name
+                .as_deref()
+                .is_some_and(|name| ANIMATED_IMAGE_FORMATS.contains(&name)),
+            audio_stream: None,
+            audio_tracks,
+            chapters: Self::compute_chapters(ffprobe),
+            crop: None,
             dimensions,
             display_aspect_ratio,
             duration,
             duration_seconds,
+            duration_source,
+            duration_doubtful,
             filename,
-            frame_rate,
+            hdr,
+            image_sequence: None,
+            interlaced,
+            frame_rate: avg_frame_rate.or(r_frame_rate),
+            avg_frame_rate,
+            r_frame_rate,
+            variable_frame_rate,
+            rotation,
             sample_aspect_ratio,
             size,
             size_bytes,
+            start_time: Self::compute_start_time(ffprobe),
+            subtitle_tracks,
             video_codec,
             video_codec_long,
+            video_stream: video_stream_index,
+        })
+    }
+}
+
+/// The ffmpeg and ffprobe executables used for probing and capture, and
+/// how long probes and captures may take before they are killed.
+#[derive(Clone, Debug)]
+pub struct Executables {
+    pub ffmpeg: String,
+    pub ffprobe: String,
+    pub timeout: Option<Duration>,
+}
+
+impl Executables {
+    pub fn ffmpeg(&self) -> Command {
+        let mut command = Command::new(&self.ffmpeg);
+        command.arg("-hide_banner");
+        command
+    }
+
+    pub fn ffprobe(&self) -> Command {
+        let mut command = Command::new(&self.ffprobe);
+        command.arg("-hide_banner");
+        command
+    }
+
+    /// Run `command` with this timeout, reporting a missing executable as
+    /// `VcsrError::ExecutableNotFound`.
+    pub fn run(&self, command: &mut Command) -> Result<Output, VcsrError> {
+        Self::run_with(command, self.timeout)
+    }
+
+    /// Run `command`, which reads the whole file, with the timeout grown
+    /// by `WHOLE_FILE_TIMEOUT_FACTOR` per second of the `duration_seconds`
+    /// long file, as it takes as long as the file is long.
+    pub fn run_whole_file(
+        &self,
+        command: &mut Command,
+        duration_seconds: f32,
+    ) -> Result<Output, VcsrError> {
+        let timeout = self.timeout.map(|timeout| {
+            timeout + Duration::from_secs_f32(duration_seconds.max(0.0) * WHOLE_FILE_TIMEOUT_FACTOR)
+        });
+        Self::run_with(command, timeout)
+    }
+
+    fn run_with(command: &mut Command, timeout: Option<Duration>) -> Result<Output, VcsrError> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        process::run(command, timeout).map_err(|e| match e {
+            VcsrError::IoError(e) if e.kind() == io::ErrorKind::NotFound => {
+                VcsrError::ExecutableNotFound(program)
+            }
+            e => e,
         })
     }
+
+    /// Version of ffmpeg, asked once per run, or `unknown` when ffmpeg
+    /// cannot be run.
+    #[cfg(not(feature = "libav"))]
+    pub fn version(&self) -> String {
+        static VERSION: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+        VERSION
+            .get_or_init(|| match self.run(self.ffmpeg().arg("-version")) {
+                Ok(output) => Capabilities::parse_version(&String::from_utf8_lossy(&output.stdout)),
+                Err(_) => String::from("unknown"),
+            })
+            .clone()
+    }
+
+    /// Versions of the FFmpeg libraries vcsr is built with.
+    #[cfg(feature = "libav")]
+    pub fn version(&self) -> String {
+        crate::native::version()
+    }
+
+    /// Check ffprobe runs, and ask ffmpeg for its version and the filters
+    /// it was built with.
+    pub fn capabilities(&self) -> Result<Capabilities, VcsrError> {
+        self.run(self.ffprobe().arg("-version"))?;
+
+        let version = self.run(self.ffmpeg().arg("-version"))?;
+        let version = Capabilities::parse_version(&String::from_utf8_lossy(&version.stdout));
+
+        let filters = self.run(self.ffmpeg().arg("-filters"))?;
+        let filters = Capabilities::parse_filters(&String::from_utf8_lossy(&filters.stdout));
+
+        Ok(Capabilities { version, filters })
+    }
+}
+
+/// What the installed ffmpeg supports.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    pub version: String,
+    pub filters: Vec<String>,
+}
+
+impl Capabilities {
+    /// The version in the first line of `ffmpeg -version`.
+    pub fn parse_version(output: &str) -> String {
+        output
+            .split_whitespace()
+            .nth(2)
+            .unwrap_or("unknown")
+            .to_string()
+    }
+
+    /// Filter names listed by `ffmpeg -filters`.
+    pub fn parse_filters(output: &str) -> Vec<String> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let columns: Vec<&str> = line.split_whitespace().collect();
+                match columns[..] {
+                    [_, name, io, ..] if io.contains("->") => Some(name.to_string()),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn has_filter(&self, filter: &str) -> bool {
+        self.filters.iter().any(|f| f == filter)
+    }
+
+    /// Fail with `VcsrError::MissingFilter` unless ffmpeg has `filter`,
+    /// which `feature` needs.
+    pub fn require_filter(&self, filter: &str, feature: &str) -> Result<(), VcsrError> {
+        if self.has_filter(filter) {
+            Ok(())
+        } else {
+            Err(VcsrError::MissingFilter {
+                filter: filter.to_string(),
+                feature: feature.to_string(),
+                version: self.version.clone(),
+            })
+        }
+    }
+}
+
+/// A source of video frames. `MediaCapture` implements this by running
+/// ffmpeg, other decoders can be used by implementing it themselves.
+pub trait FrameSource: Sync {
+    /// Capture a frame at `time` seconds with given width and height.
+    fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError>;
+
+    /// Capture a frame at each of `times`, in order. Sources that can
+    /// extract several frames at once should override this.
+    fn capture_all(
+        &self,
+        times: &[f32],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        times
+            .iter()
+            .map(|time| self.capture(*time, width, height))
+            .collect()
+    }
+
+    /// Sorted times of every keyframe in the video, in seconds. An empty
+    /// index means keyframe positions are unknown.
+    fn keyframes(&self) -> Result<Vec<f32>, VcsrError> {
+        Ok(vec![])
+    }
+
+    /// Sorted times, in seconds, of frames whose scene change score is
+    /// above `threshold`. An empty list means no shot boundaries are known.
+    fn scene_changes(&self, _threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        Ok(vec![])
+    }
 }
 
+#[derive(Clone)]
 pub struct MediaCapture {
     path: String,
     accurate: bool,
     skip_delay_seconds: f32,
     frame_type: Option<String>,
+    video_stream: u32,
+    rotation: u32,
+    deinterlace: bool,
+    tonemap: Tonemap,
+    input_options: Vec<(String, String)>,
+    start_time: f64,
+    duration_seconds: f32,
+    executables: Executables,
 }
 
 impl MediaCapture {
@@ -390,142 +1354,751 @@ impl MediaCapture {
         accurate: bool,
         skip_delay_seconds: f32,
         frame_type: Option<String>,
+        video_stream: u32,
+        rotation: u32,
+        executables: Executables,
     ) -> MediaCapture {
         MediaCapture {
             path,
             accurate,
             skip_delay_seconds,
             frame_type,
+            video_stream,
+            rotation,
+            deinterlace: false,
+            tonemap: Tonemap::Off,
+            input_options: vec![],
+            start_time: 0.0,
+            duration_seconds: 0.0,
+            executables,
         }
     }
 
+    /// Open the video with the demuxer `input_options`, as needed to read
+    /// an image sequence.
+    pub fn with_input_options(mut self, input_options: Vec<(String, String)>) -> MediaCapture {
+        self.input_options = input_options;
+        self
+    }
+
+    /// Deinterlace captures when `deinterlace` is set.
+    pub fn with_deinterlace(mut self, deinterlace: bool) -> MediaCapture {
+        self.deinterlace = deinterlace;
+        self
+    }
+
+    /// Count times from `start_time`, the start time of the container, as
+    /// the ffmpeg CLI does for `-ss` but ffprobe does not for packets.
+    pub fn with_start_time(mut self, start_time: f64) -> MediaCapture {
+        self.start_time = start_time;
+        self
+    }
+
+    /// Give passes over the whole `duration_seconds` long file time to
+    /// finish, see `Executables::run_whole_file`.
+    pub fn with_duration(mut self, duration_seconds: f32) -> MediaCapture {
+        self.duration_seconds = duration_seconds;
+        self
+    }
+
+    /// Tone map captures to SDR with `tonemap`, which should only be used
+    /// for HDR video.
+    pub fn with_tonemap(mut self, tonemap: Tonemap) -> MediaCapture {
+        self.tonemap = tonemap;
+        self
+    }
+
     /// Capture a frame at given time with given width and height
-    /// using ffmpeg.
+    /// using the FFmpeg libraries.
+    #[cfg(feature = "libav")]
     pub fn make_capture(
         &self,
         time: &str,
         width: u64,
         height: u64,
-        out_path: Option<&str>,
-    ) -> Result<(), VcsrError> {
-        let skip_delay = MediaInfo::pretty_duration(self.skip_delay_seconds, false, true);
-        let out_path = match out_path {
-            Some(o) => o,
-            None => "out.jpg",
-        };
+    ) -> Result<RgbaImage, VcsrError> {
+        let times = [MediaInfo::pretty_to_seconds(time)?];
+        let mut images = crate::native::capture_frames(
+            &self.path,
+            &self.input_options,
+            self.video_stream as usize,
+            &times,
+            width,
+            height,
+            self.frame_type.as_deref(),
+            self.native_filters().as_deref(),
+            self.rotation,
+        )?;
+        Ok(images.remove(0))
+    }
 
-        let frame_type_string: String;
-        let mut select_args = match &self.frame_type {
-            Some(frame_type) => {
-                if frame_type == "key" {
-                    vec!["-vf", "select=key"]
-                } else {
-                    frame_type_string = format!("\'select=eq(frame_type\\,{})\'", frame_type);
-                    vec!["-vf", &frame_type_string]
-                }
-            }
-            None => Vec::new(),
-        };
+    /// Capture a frame at each of the given times with given width and
+    /// height using the FFmpeg libraries, opening the file only once.
+    #[cfg(feature = "libav")]
+    pub fn make_captures(
+        &self,
+        times: &[String],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        let times: Vec<f32> = times
+            .iter()
+            .map(|time| MediaInfo::pretty_to_seconds(time))
+            .collect::<Result<_, _>>()?;
+        crate::native::capture_frames(
+            &self.path,
+            &self.input_options,
+            self.video_stream as usize,
+            &times,
+            width,
+            height,
+            self.frame_type.as_deref(),
+            self.native_filters().as_deref(),
+            self.rotation,
+        )
+    }
 
-        let time_seconds;
-        let skip_time_seconds;
-        let skip_time;
-        let mut args = if !self.accurate {
-            vec!["-ss", time, "-i", &self.path]
-        } else {
-            time_seconds = MediaInfo::pretty_to_seconds(time)?;
-            skip_time_seconds = time_seconds - self.skip_delay_seconds;
-            skip_time = MediaInfo::pretty_duration(skip_time_seconds, false, true);
-            if skip_time_seconds < 0.0 {
-                vec!["-ss", time, "-i", &self.path]
-            } else {
-                vec!["-ss", &skip_time, "-i", &self.path, "-ss", &skip_delay]
-            }
-        };
+    /// Filters applied to decoded frames before they are scaled.
+    #[cfg(feature = "libav")]
+    fn native_filters(&self) -> Option<String> {
+        let filters: Vec<String> = self
+            .deinterlace_filter()
+            .into_iter()
+            .chain(self.tonemap.filter())
+            .collect();
+        Some(filters.join(",")).filter(|filters| !filters.is_empty())
+    }
+
+    /// Capture a frame at given time with given width and height
+    /// using ffmpeg. The frame is streamed back as raw RGBA over stdout
+    /// rather than written to disk.
+    #[cfg(not(feature = "libav"))]
+    pub fn make_capture(
+        &self,
+        time: &str,
+        width: u64,
+        height: u64,
+    ) -> Result<RgbaImage, VcsrError> {
+        let (mut args, mut output_seek_args) = self.seek_args(time)?;
+        args.append(&mut output_seek_args);
 
-        let width_x_height = format!("{}x{}", width, height);
         debug!("creating image with width {width} and height {height}");
-        args.append(&mut vec!["-vframes", "1", "-s", &width_x_height]);
-        args.append(&mut select_args);
-        args.append(&mut vec!["-y", out_path]);
+        args.append(&mut vec![
+            String::from("-map"),
+            format!("0:{}", self.video_stream),
+            String::from("-vframes"),
+            String::from("1"),
+            String::from("-s"),
+            format!("{}x{}", width, height),
+        ]);
+        let filters: Vec<String> = self
+            .deinterlace_filter()
+            .into_iter()
+            .chain(self.frame_type_filter())
+            .chain(self.tonemap.filter())
+            .chain(self.rotation_filter())
+            .collect();
+        if !filters.is_empty() {
+            args.append(&mut vec![String::from("-vf"), filters.join(",")]);
+        }
+        args.append(&mut Self::rawvideo_args());
 
+        let output = self.run_ffmpeg(args)?;
+        Self::split_frames(output, time, width, height, 1).map(|mut frames| frames.remove(0))
+    }
+
+    /// Capture a frame at each of the given times with given width and
+    /// height using a single ffmpeg process. The file is opened once,
+    /// seeked to the first time and decoded on from there, and a `select`
+    /// filter keeps the first frame at or after each time. Decoding runs
+    /// through the file, so accurate mode's output seek is not needed.
+    #[cfg(not(feature = "libav"))]
+    pub fn make_captures(
+        &self,
+        times: &[String],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        if times.is_empty() {
+            return Ok(vec![]);
+        }
+        let seconds: Vec<f32> = times
+            .iter()
+            .map(|time| MediaInfo::pretty_to_seconds(time))
+            .collect::<Result<_, _>>()?;
+        let mut distinct = seconds.clone();
+        distinct.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        distinct.dedup();
+        let start = distinct[0];
+
+        let mut args = vec![
+            String::from("-ss"),
+            MediaInfo::pretty_duration(start, false, true),
+            String::from("-noautorotate"),
+        ];
+        args.append(&mut option_args(&self.input_options));
+        args.append(&mut vec![String::from("-i"), self.path.clone()]);
+
+        // Frame times count from the seek, and each time selects the first
+        // frame reaching it, allowing for times rounded to the millisecond.
+        let select = distinct
+            .iter()
+            .map(|time| {
+                let time = (time - start - 0.0005).max(0.0);
+                format!(
+                    "gte(t\\,{:.4})*(isnan(prev_selected_t)+lt(prev_selected_t\\,{:.4}))",
+                    time, time
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("+");
+        let filters: Vec<String> = self
+            .deinterlace_filter()
+            .into_iter()
+            .chain(self.frame_type_filter())
+            .chain(Some(format!("select=gt({}\\,0)", select)))
+            .chain(self.tonemap.filter())
+            .chain(self.rotation_filter())
+            .chain(Some(format!("scale={}:{},setsar=1", width, height)))
+            .collect();
+        args.append(&mut vec![
+            String::from("-map"),
+            format!("0:{}", self.video_stream),
+            String::from("-vf"),
+            filters.join(","),
+            String::from("-vsync"),
+            String::from("0"),
+            String::from("-vframes"),
+            distinct.len().to_string(),
+        ]);
+        args.append(&mut Self::rawvideo_args());
+
+        debug!(
+            "creating {} images with width {width} and height {height}",
+            distinct.len()
+        );
         debug!("{}", args.join(" "));
+        let output = self.executables.run_whole_file(
+            self.executables.ffmpeg().args(args),
+            distinct[distinct.len() - 1] - start,
+        )?;
+        let images = Self::split_frames(output, &times.join(", "), width, height, distinct.len())?;
+        Ok(seconds
+            .iter()
+            .map(|time| images[distinct.partition_point(|t| t < time)].clone())
+            .collect())
+    }
 
-        let output = Command::new("ffmpeg")
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .args(args)
-            .output()?;
-        if !output.status.success() {
-            error!("ffmpeg error: {}", str::from_utf8(&output.stderr).unwrap());
-        }
-        Ok(())
-    }
-
-    pub fn compute_avg_colour(image_path: &str) -> Result<f32, VcsrError> {
-        if Path::new(image_path).exists() {
-            let image = image::open(image_path).unwrap().to_rgba8();
-            let rgbs: (f32, f32, f32) =
-                image
-                    .enumerate_pixels()
-                    .fold((0.0, 0.0, 0.0), |acc, (_, _, p)| match p {
-                        image::Rgba(rgba) => (
-                            acc.0 + rgba[0] as f32,
-                            acc.1 + rgba[1] as f32,
-                            acc.2 + rgba[2] as f32,
-                        ),
-                    });
-            let size = image.width() as f32 * image.height() as f32;
-            Ok((rgbs.0 / size + rgbs.1 / size + rgbs.2 / size) / 3.0)
+    /// Input arguments seeking to `time`, and the output arguments needed
+    /// to finish the seek accurately when running in accurate mode.
+    /// Autorotation is turned off as `rotation_filter` rotates captures
+    /// whatever ffmpeg's default is.
+    fn seek_args(&self, time: &str) -> Result<(Vec<String>, Vec<String>), VcsrError> {
+        let input = |ts: &str| {
+            let mut input = vec![
+                String::from("-ss"),
+                String::from(ts),
+                String::from("-noautorotate"),
+            ];
+            input.append(&mut option_args(&self.input_options));
+            input.append(&mut vec![String::from("-i"), self.path.clone()]);
+            input
+        };
+
+        if !self.accurate {
+            return Ok((input(time), vec![]));
+        }
+
+        let time_seconds = MediaInfo::pretty_to_seconds(time)?;
+        let skip_time_seconds = time_seconds - self.skip_delay_seconds;
+        if skip_time_seconds < 0.0 {
+            Ok((input(time), vec![]))
+        } else {
+            let skip_time = MediaInfo::pretty_duration(skip_time_seconds, false, true);
+            let skip_delay = MediaInfo::pretty_duration(self.skip_delay_seconds, false, true);
+            Ok((input(&skip_time), vec![String::from("-ss"), skip_delay]))
+        }
+    }
+
+    /// Every setting that changes what is captured, so cached captures are
+    /// only reused with the same settings.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "{} accurate={} skip_delay={} frame_type={:?} stream={} rotation={} deinterlace={} tonemap={:?} input={:?} start={}",
+            if cfg!(feature = "libav") {
+                "libav"
+            } else {
+                "ffmpeg"
+            },
+            self.accurate,
+            self.skip_delay_seconds,
+            self.frame_type,
+            self.video_stream,
+            self.rotation,
+            self.deinterlace,
+            self.tonemap,
+            self.input_options,
+            self.start_time
+        )
+    }
+
+    /// Filter restricting captures to the requested frame type, if any.
+    fn frame_type_filter(&self) -> Option<String> {
+        self.frame_type.as_ref().map(|frame_type| {
+            if frame_type == "key" {
+                String::from("select=key")
+            } else {
+                format!("select=eq(pict_type\\,{})", frame_type)
+            }
+        })
+    }
+
+    /// Filter deinterlacing captures, if enabled. It comes first so it sees
+    /// the fields of neighbouring frames.
+    fn deinterlace_filter(&self) -> Option<String> {
+        if self.deinterlace {
+            Some(String::from("yadif"))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Cannot compute average colour as capture file was not created",
+            None
+        }
+    }
+
+    /// Filter turning captures upright, if the stream is rotated.
+    fn rotation_filter(&self) -> Option<String> {
+        match self.rotation {
+            90 => Some(String::from("transpose=clock")),
+            180 => Some(String::from("hflip,vflip")),
+            270 => Some(String::from("transpose=cclock")),
+            _ => None,
+        }
+    }
+
+    /// Output arguments streaming raw RGBA frames to stdout.
+    fn rawvideo_args() -> Vec<String> {
+        vec![
+            String::from("-f"),
+            String::from("rawvideo"),
+            String::from("-pix_fmt"),
+            String::from("rgba"),
+            String::from("pipe:1"),
+        ]
+    }
+
+    /// Split the raw RGBA bytes ffmpeg wrote for `timestamp` into `count`
+    /// images of the given size. Fails with `VcsrError::CaptureFailed` if
+    /// ffmpeg exited with an error or did not write every frame.
+    fn split_frames(
+        output: Output,
+        timestamp: &str,
+        width: u64,
+        height: u64,
+        count: usize,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        let frame_size = (width * height * 4) as usize;
+        let raw = output.stdout;
+        if !output.status.success() || frame_size == 0 || raw.len() != frame_size * count {
+            let mut stderr = capture_error_lines(&output.stderr);
+            if output.status.success() {
+                stderr = format!(
+                    "expected {} captures of {}x{} but got {} bytes. {}",
+                    count,
+                    width,
+                    height,
+                    raw.len(),
+                    stderr
+                );
+            }
+            return Err(VcsrError::CaptureFailed {
+                timestamp: timestamp.to_string(),
+                exit_code: output.status.code(),
+                stderr,
+            });
+        }
+        Ok(raw
+            .chunks_exact(frame_size)
+            .map(|chunk| RgbaImage::from_raw(width as u32, height as u32, chunk.to_vec()).unwrap())
+            .collect())
+    }
+
+    /// Build an index of keyframe times by reading the packet flags of
+    /// the video stream with the FFmpeg libraries.
+    #[cfg(feature = "libav")]
+    pub fn keyframe_index(&self) -> Result<Vec<f32>, VcsrError> {
+        crate::native::keyframe_times(&self.path, &self.input_options, self.video_stream as usize)
+    }
+
+    /// Build an index of keyframe times by reading the packet flags of
+    /// the video stream with ffprobe. Only the container is demuxed, no
+    /// frames are decoded.
+    #[cfg(not(feature = "libav"))]
+    pub fn keyframe_index(&self) -> Result<Vec<f32>, VcsrError> {
+        let output = self.executables.run_whole_file(
+            self.executables
+                .ffprobe()
+                .arg("-v")
+                .arg("quiet")
+                .arg("-select_streams")
+                .arg(self.video_stream.to_string())
+                .arg("-show_entries")
+                .arg("packet=pts_time,flags")
+                .arg("-of")
+                .arg("csv=print_section=0")
+                .args(option_args(&self.input_options))
+                .arg(&self.path),
+            self.duration_seconds,
+        )?;
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ffprobe cannot read the packets",
             )
-            .into())
+            .into());
         }
+        let stdout = str::from_utf8(&output.stdout).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ffprobe returned invalid packets",
+            )
+        })?;
+
+        let mut keyframes: Vec<f32> = stdout
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split(',');
+                let pts_time = fields.next()?.parse::<f64>().ok()?;
+                let flags = fields.next()?;
+                if flags.contains('K') {
+                    Some((pts_time - self.start_time) as f32)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        keyframes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        keyframes.dedup();
+        Ok(keyframes)
     }
 
-    pub fn compute_blurrines(image_path: &str) -> Result<f32, VcsrError> {
-        if Path::new(image_path).exists() {
-            let f = std::fs::File::open(image_path).unwrap();
-            drop(f);
+    /// The span of the packet timestamps of stream `stream`, read with the
+    /// FFmpeg libraries.
+    #[cfg(feature = "libav")]
+    pub fn scan_duration(&self, stream: u32) -> Result<f32, VcsrError> {
+        crate::native::packet_duration(&self.path, &self.input_options, stream as usize)
+    }
 
-            let image = image::open(image_path).unwrap().to_luma8();
-            let mut input: Vec<Complex<f32>> = image
-                .enumerate_pixels()
-                .map(|(_, _, p)| match p {
-                    image::Luma(g) => Complex {
-                        re: g[0] as f32,
-                        im: 0.0,
-                    },
-                })
-                .collect();
+    /// The span of the packet timestamps of stream `stream`, the video or
+    /// an audio only file's audio, from the first packet to the end of the
+    /// last, read with ffprobe. Like the keyframe index, only the container
+    /// is demuxed.
+    #[cfg(not(feature = "libav"))]
+    pub fn scan_duration(&self, stream: u32) -> Result<f32, VcsrError> {
+        let output = self.executables.run_whole_file(
+            self.executables
+                .ffprobe()
+                .arg("-v")
+                .arg("quiet")
+                .arg("-select_streams")
+                .arg(stream.to_string())
+                .arg("-show_entries")
+                .arg("packet=pts_time,duration_time")
+                .arg("-of")
+                .arg("csv=print_section=0")
+                .args(option_args(&self.input_options))
+                .arg(&self.path),
+            self.duration_seconds,
+        )?;
+        if !output.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ffprobe cannot read the packets",
+            )
+            .into());
+        }
+        let stdout = str::from_utf8(&output.stdout).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "ffprobe returned invalid packets",
+            )
+        })?;
+
+        let packets = stdout.lines().filter_map(|line| {
+            let mut fields = line.split(',');
+            let pts_time = fields.next()?.parse::<f32>().ok()?;
+            let duration_time = fields
+                .next()
+                .and_then(|duration| duration.parse::<f32>().ok())
+                .unwrap_or(0.0);
+            Some((pts_time, pts_time + duration_time))
+        });
+        packet_span(packets).ok_or(VcsrError::MediaError)
+    }
 
-            let mut planner = FftPlanner::new();
-            let fft = planner.plan_fft(input.len(), FftDirection::Forward);
-            fft.process(&mut input);
+    /// Decode `seconds` of audio stream `stream` from `start` as mono
+    /// samples at `sample_rate` with the FFmpeg libraries.
+    #[cfg(feature = "libav")]
+    pub fn audio_samples(
+        &self,
+        stream: u32,
+        sample_rate: u32,
+        start: f32,
+        seconds: f32,
+    ) -> Result<Vec<i16>, VcsrError> {
+        crate::native::audio_samples(
+            &self.path,
+            &self.input_options,
+            stream as usize,
+            sample_rate,
+            start,
+            seconds,
+        )
+    }
 
-            let mut collected: Vec<f32> = input
-                .into_iter()
-                .map(|c| match c {
-                    Complex { re, im: _ } => (re).abs(),
-                })
-                .collect();
-            collected.sort_by(|a, b| b.partial_cmp(&a).unwrap());
-            collected.dedup();
-            let max_freq = MediaCapture::avg9x(collected, None);
-            if max_freq > 0.0 {
-                return Ok(1.0 / max_freq);
-            } else {
-                return Ok(1.0);
+    /// Decode `seconds` of audio stream `stream` from `start` as mono
+    /// samples at `sample_rate` by having ffmpeg write them as raw 16 bit
+    /// PCM. Only the slice is decoded, so it has the timeout of a capture.
+    #[cfg(not(feature = "libav"))]
+    pub fn audio_samples(
+        &self,
+        stream: u32,
+        sample_rate: u32,
+        start: f32,
+        seconds: f32,
+    ) -> Result<Vec<i16>, VcsrError> {
+        let output = self.executables.run(
+            self.executables
+                .ffmpeg()
+                .arg("-v")
+                .arg("error")
+                .arg("-ss")
+                .arg(MediaInfo::pretty_duration(start, false, true))
+                .args(option_args(&self.input_options))
+                .arg("-i")
+                .arg(&self.path)
+                .arg("-t")
+                .arg(seconds.to_string())
+                .arg("-map")
+                .arg(format!("0:{}", stream))
+                .arg("-ac")
+                .arg("1")
+                .arg("-ar")
+                .arg(sample_rate.to_string())
+                .arg("-f")
+                .arg("s16le")
+                .arg("pipe:1"),
+        )?;
+        if !output.status.success() {
+            return Err(VcsrError::AudioError(format!(
+                "{}: {}",
+                self.path,
+                capture_error_lines(&output.stderr)
+            )));
+        }
+        Ok(output
+            .stdout
+            .chunks_exact(2)
+            .map(|sample| i16::from_le_bytes([sample[0], sample[1]]))
+            .collect())
+    }
+
+    /// Read the cues of `source` with the FFmpeg libraries.
+    #[cfg(feature = "libav")]
+    pub fn subtitle_cues(&self, source: &SubtitleSource) -> Result<Vec<SubtitleCue>, VcsrError> {
+        match source {
+            SubtitleSource::File(path) => {
+                crate::native::subtitle_cues(&path.to_string_lossy(), None)
+            }
+            SubtitleSource::Stream(index) => {
+                crate::native::subtitle_cues(&self.path, Some(*index as usize))
+            }
+        }
+    }
+
+    /// Read the cues of `source` by having ffmpeg convert it to SubRip,
+    /// which works the same for every text subtitle format.
+    #[cfg(not(feature = "libav"))]
+    pub fn subtitle_cues(&self, source: &SubtitleSource) -> Result<Vec<SubtitleCue>, VcsrError> {
+        let (input, map) = match source {
+            SubtitleSource::File(path) => {
+                (path.to_string_lossy().into_owned(), String::from("0:s:0"))
             }
+            SubtitleSource::Stream(index) => (self.path.clone(), format!("0:{}", index)),
+        };
+        let output = self.executables.run_whole_file(
+            self.executables
+                .ffmpeg()
+                .arg("-v")
+                .arg("error")
+                .arg("-i")
+                .arg(&input)
+                .arg("-map")
+                .arg(map)
+                .arg("-f")
+                .arg("srt")
+                .arg("pipe:1"),
+            self.duration_seconds,
+        )?;
+        if !output.status.success() {
+            return Err(VcsrError::SubtitleError(format!(
+                "{}: {}",
+                input,
+                capture_error_lines(&output.stderr)
+            )));
+        }
+        Ok(parse_srt(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Find shot boundaries by comparing consecutive frames with the FFmpeg
+    /// libraries.
+    #[cfg(feature = "libav")]
+    pub fn detect_scenes(&self, threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        crate::native::scene_changes(
+            &self.path,
+            &self.input_options,
+            self.video_stream as usize,
+            threshold,
+        )
+    }
+
+    /// Find shot boundaries with ffmpeg's scene change score. Frames are
+    /// downscaled before scoring as only large changes matter.
+    #[cfg(not(feature = "libav"))]
+    pub fn detect_scenes(&self, threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        let output = self.executables.run_whole_file(
+            self.executables
+                .ffmpeg()
+                .arg("-nostats")
+                .args(option_args(&self.input_options))
+                .arg("-i")
+                .arg(&self.path)
+                .arg("-map")
+                .arg(format!("0:{}", self.video_stream))
+                .arg("-vf")
+                .arg(format!(
+                    "scale=160:-2,select=gt(scene\\,{}),showinfo",
+                    threshold
+                ))
+                .arg("-f")
+                .arg("null")
+                .arg("-"),
+            self.duration_seconds,
+        )?;
+        if !output.status.success() {
+            error!("ffmpeg error: {}", String::from_utf8_lossy(&output.stderr));
+        }
+
+        let mut scenes: Vec<f32> = String::from_utf8_lossy(&output.stderr)
+            .lines()
+            .filter(|line| line.contains("Parsed_showinfo"))
+            .filter_map(|line| {
+                let pts_time = line.split("pts_time:").nth(1)?;
+                pts_time.split_whitespace().next()?.parse::<f32>().ok()
+            })
+            .collect();
+        scenes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        scenes.dedup();
+        Ok(scenes)
+    }
+
+    /// The keyframe in the sorted `keyframes` closest to `time`.
+    pub fn nearest_keyframe(keyframes: &[f32], time: f32) -> Option<f32> {
+        let after = keyframes.partition_point(|k| *k < time);
+        let before = after.checked_sub(1).map(|i| keyframes[i]);
+        match (before, keyframes.get(after)) {
+            (Some(b), Some(a)) if time - b <= a - time => Some(b),
+            (_, Some(a)) => Some(*a),
+            (b, None) => b,
+        }
+    }
+
+    /// Each of `times` moved onto the closest keyframe in the sorted
+    /// `keyframes` not already taken by an earlier time, so no two cells
+    /// show the same frame. Once every keyframe is taken, times go to the
+    /// nearest keyframe, so there is still one per time.
+    pub fn distinct_keyframes(keyframes: &[f32], times: &[f32]) -> Vec<f32> {
+        let mut taken = vec![false; keyframes.len()];
+        let mut snapped = vec![];
+        for time in times {
+            let after = keyframes.partition_point(|k| k < time);
+            let before = (0..after).rev().find(|i| !taken[*i]);
+            let after = (after..keyframes.len()).find(|i| !taken[*i]);
+            let index = match (before, after) {
+                (Some(b), Some(a)) if time - keyframes[b] <= keyframes[a] - time => b,
+                (_, Some(a)) => a,
+                (Some(b), None) => b,
+                (None, None) => {
+                    snapped.extend(Self::nearest_keyframe(keyframes, *time));
+                    continue;
+                }
+            };
+            taken[index] = true;
+            snapped.push(keyframes[index]);
+        }
+        snapped.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        snapped
+    }
+
+    /// `time` as passed to `-ss`. Times are rounded to the millisecond, so
+    /// keyframe-only captures seek half a millisecond early to not land
+    /// just after a keyframe and skip to the next one.
+    fn seek_time(&self, time: f32) -> String {
+        let time = if self.frame_type.as_deref() == Some("key") {
+            (time - 0.0005).max(0.0)
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "Cannot compute blurriness as capture file was not created",
-            )
-            .into())
+            time
+        };
+        MediaInfo::pretty_duration(time, false, true)
+    }
+
+    fn run_ffmpeg(&self, args: Vec<String>) -> Result<Output, VcsrError> {
+        debug!("{}", args.join(" "));
+
+        self.executables.run(self.executables.ffmpeg().args(args))
+    }
+
+    pub fn compute_avg_colour(image: &RgbaImage) -> f32 {
+        let rgbs: (f32, f32, f32) =
+            image
+                .enumerate_pixels()
+                .fold((0.0, 0.0, 0.0), |acc, (_, _, p)| match p {
+                    image::Rgba(rgba) => (
+                        acc.0 + rgba[0] as f32,
+                        acc.1 + rgba[1] as f32,
+                        acc.2 + rgba[2] as f32,
+                    ),
+                });
+        let size = image.width() as f32 * image.height() as f32;
+        (rgbs.0 / size + rgbs.1 / size + rgbs.2 / size) / 3.0
+    }
+
+    pub fn compute_blurrines(image: &RgbaImage) -> f32 {
+        let image = image::imageops::grayscale(image);
+        let mut input: Vec<Complex<f32>> = image
+            .enumerate_pixels()
+            .map(|(_, _, p)| match p {
+                image::Luma(g) => Complex {
+                    re: g[0] as f32,
+                    im: 0.0,
+                },
+            })
+            .collect();
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft(input.len(), FftDirection::Forward);
+        fft.process(&mut input);
+
+        let mut collected: Vec<f32> = input
+            .into_iter()
+            .map(|c| match c {
+                Complex { re, im: _ } => (re).abs(),
+            })
+            .collect();
+        collected.sort_by(|a, b| b.partial_cmp(&a).unwrap());
+        collected.dedup();
+        let max_freq = MediaCapture::avg9x(collected, None);
+        if max_freq > 0.0 {
+            1.0 / max_freq
+        } else {
+            1.0
         }
     }
 
@@ -553,6 +2126,364 @@ impl MediaCapture {
     }
 }
 
+impl FrameSource for MediaCapture {
+    /// Failed captures are not retried here, but by the caller, see
+    /// `--capture-retries`.
+    fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+        self.make_capture(&self.seek_time(time), width, height)
+    }
+
+    fn capture_all(
+        &self,
+        times: &[f32],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        let times: Vec<String> = times.iter().map(|time| self.seek_time(*time)).collect();
+        self.make_captures(&times, width, height)
+    }
+
+    fn keyframes(&self) -> Result<Vec<f32>, VcsrError> {
+        self.keyframe_index()
+    }
+
+    fn scene_changes(&self, threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        self.detect_scenes(threshold)
+    }
+}
+
+/// A frame source cropping black bars from captures of another source.
+/// Frames are captured large enough that the part left once cropped has
+/// the requested size.
+pub struct CroppedSource<'a> {
+    inner: &'a dyn FrameSource,
+    crop: Crop,
+    frame: Grid,
+}
+
+impl<'a> CroppedSource<'a> {
+    /// Crop `crop`, given in pixels of a `frame` sized picture, from every
+    /// capture of `inner`.
+    pub fn new(inner: &'a dyn FrameSource, crop: Crop, frame: Grid) -> CroppedSource<'a> {
+        CroppedSource { inner, crop, frame }
+    }
+
+    /// Size to capture at so the cropped part is `width` x `height`.
+    fn full_size(&self, width: u64, height: u64) -> Grid {
+        let grow = |size: u64, frame: u64, crop: u64| (size * frame + crop - 1) / crop.max(1);
+        Grid {
+            x: grow(width, self.frame.x, self.crop.width),
+            y: grow(height, self.frame.y, self.crop.height),
+        }
+    }
+
+    fn crop_capture(&self, image: &RgbaImage, width: u64, height: u64) -> RgbaImage {
+        let size = Grid {
+            x: u64::from(image.width()),
+            y: u64::from(image.height()),
+        };
+        let crop = self.crop.scale(&self.frame, &size);
+        let cropped = image::imageops::crop_imm(
+            image,
+            crop.x as u32,
+            crop.y as u32,
+            crop.width as u32,
+            crop.height as u32,
+        )
+        .to_image();
+        // Rounding can leave the cropped part a pixel off.
+        if cropped.dimensions() == (width as u32, height as u32) {
+            cropped
+        } else {
+            image::imageops::resize(
+                &cropped,
+                width as u32,
+                height as u32,
+                image::imageops::FilterType::Triangle,
+            )
+        }
+    }
+}
+
+impl<'a> FrameSource for CroppedSource<'a> {
+    fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+        let full = self.full_size(width, height);
+        let image = self.inner.capture(time, full.x, full.y)?;
+        Ok(self.crop_capture(&image, width, height))
+    }
+
+    fn capture_all(
+        &self,
+        times: &[f32],
+        width: u64,
+        height: u64,
+    ) -> Result<Vec<RgbaImage>, VcsrError> {
+        let full = self.full_size(width, height);
+        Ok(self
+            .inner
+            .capture_all(times, full.x, full.y)?
+            .iter()
+            .map(|image| self.crop_capture(image, width, height))
+            .collect())
+    }
+
+    fn keyframes(&self) -> Result<Vec<f32>, VcsrError> {
+        self.inner.keyframes()
+    }
+
+    fn scene_changes(&self, threshold: f32) -> Result<Vec<f32>, VcsrError> {
+        self.inner.scene_changes(threshold)
+    }
+}
+
+/// Seconds from the earliest packet start to the latest packet end, of
+/// packets given as their start and end.
+pub fn packet_span<I: Iterator<Item = (f32, f32)>>(packets: I) -> Option<f32> {
+    let (first, last) = packets.fold((None, None), |(first, last), (start, end)| {
+        (
+            Some(first.map_or(start, |first: f32| first.min(start))),
+            Some(last.map_or(end, |last: f32| last.max(end))),
+        )
+    });
+    Some(last? - first?).filter(|span| *span > 0.0)
+}
+
+/// Command line arguments setting the demuxer `options` of the input that
+/// follows them.
+fn option_args(options: &[(String, String)]) -> Vec<String> {
+    options
+        .iter()
+        .flat_map(|(key, value)| vec![format!("-{}", key), value.clone()])
+        .collect()
+}
+
+/// A numbered run of still images in one directory, such as
+/// `frame_00001.png` to `frame_00240.png`, read as a video whose frames
+/// are the images in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageSequence {
+    pub dir: PathBuf,
+    /// Name of the images up to their number.
+    pub prefix: String,
+    /// Width the numbers are padded to, or 0 when they are not padded.
+    pub digits: usize,
+    pub extension: String,
+    pub first_frame: u64,
+    pub frame_count: u64,
+    pub size_bytes: u64,
+    /// Modification time of the newest image, in nanoseconds since the
+    /// epoch, so images rendered again in place are noticed.
+    pub modified: u128,
+}
+
+impl ImageSequence {
+    /// The longest image sequence directly inside `dir`. Numbering must be
+    /// contiguous, as reading stops at the first missing image.
+    pub fn find(dir: &Path) -> Option<ImageSequence> {
+        // Number, its width in digits, and the size and modification time
+        // of each image, by prefix and extension.
+        type Images = Vec<(u64, usize, u64, u128)>;
+        let mut runs: BTreeMap<(String, String), Images> = BTreeMap::new();
+        for entry in fs::read_dir(dir).ok()?.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if !path.is_file() {
+                continue;
+            }
+            if let Some((prefix, digits, number, extension)) = Self::parse_name(&path) {
+                let metadata = entry.metadata().ok();
+                let size = metadata.as_ref().map_or(0, |metadata| metadata.len());
+                let modified = metadata
+                    .and_then(|metadata| metadata.modified().ok())
+                    .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |modified| modified.as_nanos());
+                runs.entry((prefix, extension))
+                    .or_default()
+                    .push((number, digits, size, modified));
+            }
+        }
+
+        runs.into_iter()
+            .flat_map(|((prefix, extension), frames)| {
+                // Numbers are either padded to a width, past which they may
+                // grow, or not padded at all, as with f_1 to f_10. Each
+                // width found is tried, keeping the longest run.
+                let mut widths: Vec<usize> = frames
+                    .iter()
+                    .filter(|(number, digits, _, _)| !Self::is_natural_width(*number, *digits))
+                    .map(|(_, digits, _, _)| *digits)
+                    .chain([0])
+                    .collect();
+                widths.sort_unstable();
+                widths.dedup();
+                widths.into_iter().filter_map(move |digits| {
+                    let mut frames: Vec<(u64, u64, u128)> = frames
+                        .iter()
+                        .filter(|(number, width, _, _)| Self::is_padded_to(*number, *width, digits))
+                        .map(|(number, _, size, modified)| (*number, *size, *modified))
+                        .collect();
+                    frames.sort_unstable();
+                    let first_frame = frames.first()?.0;
+                    let run: Vec<&(u64, u64, u128)> = frames
+                        .iter()
+                        .enumerate()
+                        .take_while(|(i, (number, _, _))| *number == first_frame + *i as u64)
+                        .map(|(_, frame)| frame)
+                        .collect();
+                    if run.len() < IMAGE_SEQUENCE_MIN_FRAMES {
+                        return None;
+                    }
+                    Some(ImageSequence {
+                        dir: dir.to_path_buf(),
+                        prefix: prefix.clone(),
+                        digits,
+                        extension: extension.clone(),
+                        first_frame,
+                        frame_count: run.len() as u64,
+                        size_bytes: run.iter().map(|(_, size, _)| size).sum(),
+                        modified: run.iter().map(|(_, _, modified)| *modified).max()?,
+                    })
+                })
+            })
+            .max_by_key(|sequence| sequence.frame_count)
+    }
+
+    /// Split the name of an image into the part before its number, the
+    /// width and value of the number, and its extension.
+    fn parse_name(path: &Path) -> Option<(String, usize, u64, String)> {
+        if !Self::is_image(path) {
+            return None;
+        }
+        let extension = path.extension()?.to_str()?;
+        let stem = path.file_stem()?.to_str()?;
+        let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+        let digits = stem.len() - prefix.len();
+        let number = stem[prefix.len()..].parse::<u64>().ok()?;
+        Some((prefix.to_string(), digits, number, extension.to_string()))
+    }
+
+    /// Whether `number`, written with `digits` digits, has no leading zeros.
+    fn is_natural_width(number: u64, digits: usize) -> bool {
+        number.to_string().len() == digits
+    }
+
+    /// Whether `number`, written with `digits` digits, reads as padded to
+    /// `width`, the way ffmpeg's `%0<width>d` writes it.
+    fn is_padded_to(number: u64, digits: usize, width: usize) -> bool {
+        digits == width || (digits > width && Self::is_natural_width(number, digits))
+    }
+
+    /// Whether the sequence is long enough to be taken for a video when
+    /// found in a subdirectory, rather than a few numbered stills.
+    pub fn is_long(&self) -> bool {
+        self.frame_count >= IMAGE_SEQUENCE_MIN_WALKED_FRAMES
+    }
+
+    /// Whether `path` has the extension of an image sequences are read from.
+    pub fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                IMAGE_SEQUENCE_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+            })
+    }
+
+    /// Whether `path` is named like the images of this sequence.
+    pub fn contains(&self, path: &Path) -> bool {
+        path.parent() == Some(self.dir.as_path())
+            && Self::parse_name(path).is_some_and(|(prefix, digits, number, extension)| {
+                prefix == self.prefix
+                    && Self::is_padded_to(number, digits, self.digits)
+                    && extension == self.extension
+            })
+    }
+
+    /// The `printf` style pattern ffmpeg's image2 demuxer reads the images
+    /// with.
+    pub fn pattern(&self) -> PathBuf {
+        let number = match self.digits {
+            0 => String::from("%d"),
+            digits => format!("%0{}d", digits),
+        };
+        self.dir.join(format!(
+            "{}{}.{}",
+            self.prefix.replace('%', "%%"),
+            number,
+            self.extension
+        ))
+    }
+
+    /// Demuxer options reading the images from the first one at
+    /// `frame_rate`.
+    pub fn input_options(&self, frame_rate: FrameRate) -> Vec<(String, String)> {
+        vec![
+            (
+                String::from("framerate"),
+                format!("{}/{}", frame_rate.numerator, frame_rate.denominator),
+            ),
+            (String::from("start_number"), self.first_frame.to_string()),
+        ]
+    }
+}
+
+/// Cues of a SubRip document, in the order they appear.
+pub fn parse_srt(srt: &str) -> Vec<SubtitleCue> {
+    let mut cues = vec![];
+    let mut lines = srt.lines().map(|line| line.trim_end_matches('\r'));
+    while let Some(line) = lines.next() {
+        let (start, end) = match line.split_once("-->") {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+        let parse = |time: &str| {
+            // Drop WebVTT cue settings that may follow the end time.
+            let time = time.split_whitespace().next().unwrap_or_default();
+            MediaInfo::pretty_to_seconds(&time.replace(',', ".")).ok()
+        };
+        let text: Vec<&str> = lines.by_ref().take_while(|line| !line.is_empty()).collect();
+        if let (Some(start), Some(end)) = (parse(start), parse(end)) {
+            let text = clean_cue_text(&text.join("\n"));
+            if !text.is_empty() {
+                cues.push(SubtitleCue { start, end, text });
+            }
+        }
+    }
+    cues
+}
+
+/// Strip HTML style tags and ASS override blocks from cue text and turn
+/// ASS line breaks into new lines.
+pub fn clean_cue_text(text: &str) -> String {
+    let text = text.replace("\\N", "\n").replace("\\n", "\n");
+    let mut cleaned = String::with_capacity(text.len());
+    let mut closing = None;
+    for c in text.chars() {
+        match (closing, c) {
+            (None, '<') => closing = Some('>'),
+            (None, '{') => closing = Some('}'),
+            (None, c) => cleaned.push(c),
+            (Some(end), c) if c == end => closing = None,
+            (Some(_), _) => {}
+        }
+    }
+    cleaned
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The last few lines of ffmpeg's error output, which explain a failure
+/// without the banner and stream listing that precede them.
+fn capture_error_lines(stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    let lines: Vec<&str> = stderr
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect();
+    lines[lines.len().saturating_sub(3)..].join(" ")
+}
+
 #[derive(Debug)]
 pub struct Time {
     hours: f32,
@@ -562,145 +2493,242 @@ pub struct Time {
     millis: f32,
 }
 
-#[derive(Clone, Default, Debug, Deserialize)]
-struct Disposition {
-    attached_pic: u32,
-    clean_effects: u32,
-    comment: u32,
-    default: u32,
-    dub: u32,
-    forced: u32,
-    hearing_impaired: u32,
-    karaoke: u32,
-    lyrics: u32,
-    original: u32,
-    timed_thumbnails: u32,
-    visual_impaired: u32,
-}
-
-#[derive(Clone, Default, Debug, Deserialize)]
-#[serde(rename_all = "snake_case")]
-struct StreamTags {
-    creation_time: Option<String>,
-    duration: Option<String>,
-    handler_name: Option<String>,
-    language: Option<String>,
-    rotate: Option<u32>,
-    #[serde(rename = "BPS-eng")]
-    bps_eng: String,
-    #[serde(rename = "DURATION-eng")]
-    duration_eng: String,
-    #[serde(rename = "NUMBER_OF_BYTES-eng")]
-    number_of_bytes_eng: String,
-    #[serde(rename = "NUMBER_OF_FRAMES-eng")]
-    number_of_frames_eng: String,
-    #[serde(rename = "_STATISTICS_TAGS-eng")]
-    statistics_tags_eng: String,
-    #[serde(rename = "_STATISTICS_WRITING_APP-eng")]
-    statistics_writing_app_eng: String,
-    #[serde(rename = "_STATISTICS_WRITING_DATE_UTC-eng")]
-    statistics_writing_date_utc_eng: String,
+/// The model of `ffprobe -print_format json -show_format -show_streams
+/// -show_chapters`. Every field is optional so output from older or newer
+/// ffprobe versions still parses, and anything not modelled here is kept
+/// in the `extra` maps.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Ffprobe {
+    pub streams: Vec<Stream>,
+    pub chapters: Vec<Chapter>,
+    pub format: Format,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-#[derive(Clone, Default, Debug, Deserialize)]
-pub struct GenericStream {}
-
 #[derive(Clone, Debug, Deserialize)]
-#[serde(tag = "codec_type")]
+#[serde(from = "StreamStruct")]
 pub enum Stream {
-    #[serde(rename = "video")]
     VideoStream(StreamStruct),
-    #[serde(rename = "audio")]
     AudioStream(StreamStruct),
-    #[serde(rename = "subtitle")]
     SubtitleStream(StreamStruct),
-    #[serde(rename = "data")]
     DataStream(StreamStruct),
+    AttachmentStream(StreamStruct),
+    /// A stream whose `codec_type` is missing or not known to vcsr.
+    OtherStream(StreamStruct),
+}
+
+impl From<StreamStruct> for Stream {
+    fn from(stream: StreamStruct) -> Self {
+        match stream.codec_type.as_deref() {
+            Some("video") => Stream::VideoStream(stream),
+            Some("audio") => Stream::AudioStream(stream),
+            Some("subtitle") => Stream::SubtitleStream(stream),
+            Some("data") => Stream::DataStream(stream),
+            Some("attachment") => Stream::AttachmentStream(stream),
+            _ => Stream::OtherStream(stream),
+        }
+    }
+}
+
+impl Stream {
+    /// The stream's fields, whatever its type.
+    pub fn stream(&self) -> &StreamStruct {
+        match self {
+            Stream::VideoStream(stream)
+            | Stream::AudioStream(stream)
+            | Stream::SubtitleStream(stream)
+            | Stream::DataStream(stream)
+            | Stream::AttachmentStream(stream)
+            | Stream::OtherStream(stream) => stream,
+        }
+    }
 }
 
 #[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
 pub struct StreamStruct {
-    avg_frame_rate: Option<String>,
-    bits_per_raw_sample: Option<String>,
-    bit_rate: Option<String>,
-    chroma_location: Option<String>,
-    codec_long_name: Option<String>,
+    pub avg_frame_rate: Option<String>,
+    pub bits_per_raw_sample: Option<String>,
+    pub bits_per_sample: Option<u32>,
+    pub bit_rate: Option<String>,
+    pub channel_layout: Option<String>,
+    pub channels: Option<u32>,
+    pub chroma_location: Option<String>,
+    pub codec_long_name: Option<String>,
     pub codec_name: Option<String>,
-    codec_tag: Option<String>,
-    codec_tag_string: Option<String>,
-    codec_time_base: Option<String>,
-    codec_type: Option<String>,
-    coded_height: Option<u32>,
-    coded_width: Option<u32>,
-    color_primaries: Option<String>,
-    color_range: Option<String>,
-    color_space: Option<String>,
-    color_transfer: Option<String>,
-    display_aspect_ratio: Option<String>,
-    #[serde(skip)]
-    disposition: Disposition,
-    duration_ts: Option<u64>,
-    duration: Option<String>,
-    field_order: Option<String>,
-    has_b_frames: Option<u32>,
-    height: Option<u64>,
-    index: Option<u32>,
-    is_avc: Option<String>,
-    level: Option<u32>,
-    nal_length_size: Option<String>,
-    pix_fmt: Option<String>,
-    profile: Option<String>,
-    r_frame_rate: Option<String>,
-    refs: Option<u32>,
+    pub codec_tag: Option<String>,
+    pub codec_tag_string: Option<String>,
+    pub codec_time_base: Option<String>,
+    pub codec_type: Option<String>,
+    pub coded_height: Option<u32>,
+    pub coded_width: Option<u32>,
+    pub color_primaries: Option<String>,
+    pub color_range: Option<String>,
+    pub color_space: Option<String>,
+    pub color_transfer: Option<String>,
+    pub display_aspect_ratio: Option<String>,
+    pub disposition: Disposition,
+    pub duration_ts: Option<u64>,
+    pub duration: Option<String>,
+    pub field_order: Option<String>,
+    pub has_b_frames: Option<u32>,
+    pub height: Option<u64>,
+    pub id: Option<String>,
+    pub index: Option<u32>,
+    pub is_avc: Option<String>,
+    pub level: Option<i32>,
+    pub nal_length_size: Option<String>,
+    pub nb_frames: Option<String>,
+    pub pix_fmt: Option<String>,
+    pub profile: Option<String>,
+    pub r_frame_rate: Option<String>,
+    pub refs: Option<u32>,
     #[serde(default = "default_sample_aspect_ratio")]
-    sample_aspect_ratio: Option<String>,
-    sample_rate: Option<String>,
-    start_pts: Option<u32>,
-    start_time: Option<String>,
-    #[serde(skip)]
-    tags: StreamTags,
-    time_base: Option<String>,
-    width: Option<u64>,
+    pub sample_aspect_ratio: Option<String>,
+    pub sample_fmt: Option<String>,
+    pub sample_rate: Option<String>,
+    pub side_data_list: Vec<SideData>,
+    pub start_pts: Option<i64>,
+    pub start_time: Option<String>,
+    pub tags: StreamTags,
+    pub time_base: Option<String>,
+    pub width: Option<u64>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
+fn default_sample_aspect_ratio() -> Option<String> {
+    Some("1:1".to_string())
+}
+
+/// Flags ffprobe reports as 0 or 1.
 #[derive(Clone, Default, Debug, Deserialize)]
-struct SideDataType {
-    side_data_type: String,
+#[serde(default)]
+pub struct Disposition {
+    pub attached_pic: u32,
+    pub captions: u32,
+    pub clean_effects: u32,
+    pub comment: u32,
+    pub default: u32,
+    pub dependent: u32,
+    pub descriptions: u32,
+    pub dub: u32,
+    pub forced: u32,
+    pub hearing_impaired: u32,
+    pub karaoke: u32,
+    pub lyrics: u32,
+    pub metadata: u32,
+    pub original: u32,
+    pub still_image: u32,
+    pub timed_thumbnails: u32,
+    pub visual_impaired: u32,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
-fn default_sample_aspect_ratio() -> Option<String> {
-    Some("1:1".to_string())
+/// Stream tags. Matroska statistics tags such as `BPS` or `DURATION-eng`
+/// end up in `extra`.
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct StreamTags {
+    pub creation_time: Option<String>,
+    #[serde(alias = "DURATION")]
+    pub duration: Option<String>,
+    pub encoder: Option<String>,
+    pub handler_name: Option<String>,
+    pub language: Option<String>,
+    /// Rotation in degrees, written by older muxers; newer ffprobe reports
+    /// a display matrix in `side_data_list` instead.
+    #[serde(deserialize_with = "lenient_number")]
+    pub rotate: Option<i32>,
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// An entry of a stream's `side_data_list`, such as a display matrix or
+/// HDR mastering metadata.
+#[derive(Clone, Default, Debug, Deserialize)]
+#[serde(default)]
+pub struct SideData {
+    pub side_data_type: String,
+    pub displaymatrix: Option<String>,
+    #[serde(deserialize_with = "lenient_number")]
+    pub rotation: Option<f32>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct Format {
-    bit_rate: Option<String>,
-    pub duration: String,
-    filename: String,
-    format_long_name: String,
-    format_name: String,
-    nb_programs: i32,
-    nb_streams: i32,
-    probe_score: i32,
-    size: String,
-    start_time: String,
-    #[serde(skip)]
-    tags: FormatTags,
+    pub bit_rate: Option<String>,
+    pub duration: Option<String>,
+    pub filename: String,
+    pub format_long_name: Option<String>,
+    pub format_name: Option<String>,
+    pub nb_programs: Option<i32>,
+    pub nb_streams: Option<i32>,
+    pub probe_score: Option<i32>,
+    pub size: Option<String>,
+    pub start_time: Option<String>,
+    pub tags: FormatTags,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
-struct FormatTags {
-    creation_time: Option<String>,
-    compatible_brands: Option<String>,
-    encoder: Option<String>,
-    major_brand: Option<String>,
-    minor_version: Option<String>,
+#[serde(default)]
+pub struct FormatTags {
+    pub comment: Option<String>,
+    pub compatible_brands: Option<String>,
+    pub creation_time: Option<String>,
+    pub encoder: Option<String>,
+    pub major_brand: Option<String>,
+    pub minor_version: Option<String>,
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
 }
 
+/// A chapter, with `start` and `end` in units of `time_base` and
+/// `start_time` and `end_time` in seconds.
 #[derive(Clone, Debug, Default, Deserialize)]
-pub struct Ffprobe {
-    streams: Vec<Stream>,
-    pub format: Format,
+#[serde(default)]
+pub struct Chapter {
+    pub id: i64,
+    pub time_base: Option<String>,
+    pub start: i64,
+    pub start_time: Option<String>,
+    pub end: i64,
+    pub end_time: Option<String>,
+    pub tags: ChapterTags,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ChapterTags {
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+/// Accept a number written either as a JSON number or a string, as
+/// ffprobe does for tags, and ignore values that are neither.
+fn lenient_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(number) => number.to_string().parse().ok(),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    })
 }
 
 #[derive(Clone, Debug)]
@@ -723,6 +2751,106 @@ impl FromStr for MetadataPosition {
     }
 }
 
+/// How captures are extracted from the video.
+#[derive(Clone, Debug, PartialEq, clap::ValueEnum)]
+pub enum CaptureMode {
+    /// Spawn one ffmpeg process per timestamp.
+    PerTimestamp,
+    /// Extract every timestamp with a single ffmpeg process decoding the
+    /// file once.
+    SinglePass,
+}
+
+/// What is drawn in each cell for audio-only files.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum AudioView {
+    /// The peaks of the audio over time.
+    Waveform,
+    /// The loudness of each frequency over time.
+    Spectrogram,
+    /// The waveform above the spectrogram.
+    Both,
+}
+
+/// When captures are deinterlaced.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Deinterlace {
+    /// Deinterlace videos whose field order is interlaced.
+    Auto,
+    /// Deinterlace every video.
+    On,
+    /// Never deinterlace.
+    Off,
+}
+
+impl Deinterlace {
+    /// Whether a video that is `interlaced` or not should be deinterlaced.
+    pub fn applies(&self, interlaced: bool) -> bool {
+        match self {
+            Deinterlace::Auto => interlaced,
+            Deinterlace::On => true,
+            Deinterlace::Off => false,
+        }
+    }
+}
+
+/// How HDR captures are tone mapped to SDR, named after the algorithms of
+/// ffmpeg's tonemap filter.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Tonemap {
+    /// Capture HDR frames as they are.
+    Off,
+    Clip,
+    Linear,
+    Gamma,
+    Reinhard,
+    Hable,
+    Mobius,
+}
+
+impl Tonemap {
+    /// Filter chain converting HDR frames to BT.709 SDR with this
+    /// algorithm, or `None` when tone mapping is off.
+    pub fn filter(&self) -> Option<String> {
+        let algorithm = match self {
+            Tonemap::Off => return None,
+            Tonemap::Clip => "clip",
+            Tonemap::Linear => "linear",
+            Tonemap::Gamma => "gamma",
+            Tonemap::Reinhard => "reinhard",
+            Tonemap::Hable => "hable",
+            Tonemap::Mobius => "mobius",
+        };
+        Some(format!(
+            "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap=tonemap={}:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+            algorithm
+        ))
+    }
+}
+
+/// The clock timestamps are labelled with.
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum TimestampBase {
+    /// Time from the start of the file.
+    Media,
+    /// The presentation timestamps stored in the file, which are offset by
+    /// its start time.
+    Presentation,
+}
+
+impl TimestampBase {
+    /// Label of a capture `timestamp` seconds into a file starting at
+    /// `start_time`.
+    pub fn label(&self, timestamp: f32, start_time: f64) -> String {
+        match self {
+            TimestampBase::Media => MediaInfo::pretty_duration(timestamp, true, false),
+            TimestampBase::Presentation => {
+                MediaInfo::pretty_time(timestamp as f64 + start_time, false)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, clap::ValueEnum)]
 pub enum TimestampPosition {
     North,
@@ -772,4 +2900,548 @@ mod tests {
             size = size * 1024.0;
         }
     }
+
+    struct SolidSource;
+
+    impl FrameSource for SolidSource {
+        fn capture(&self, time: f32, width: u64, height: u64) -> Result<RgbaImage, VcsrError> {
+            let shade = time as u8;
+            Ok(RgbaImage::from_pixel(
+                width as u32,
+                height as u32,
+                image::Rgba([shade, shade, shade, 255]),
+            ))
+        }
+    }
+
+    #[test]
+    fn crop_finds_black_bars() {
+        let mut letterboxed = RgbaImage::from_pixel(40, 30, image::Rgba([0, 0, 0, 255]));
+        for y in 5..25 {
+            for x in 0..40 {
+                letterboxed.put_pixel(x, y, image::Rgba([200, 120, 60, 255]));
+            }
+        }
+        let crop = Crop::of_image(&letterboxed, CROP_DETECT_LIMIT).unwrap();
+        assert_eq!(
+            crop,
+            Crop {
+                x: 0,
+                y: 5,
+                width: 40,
+                height: 20
+            }
+        );
+        let black = RgbaImage::from_pixel(40, 30, image::Rgba([0, 0, 0, 255]));
+        assert_eq!(Crop::of_image(&black, CROP_DETECT_LIMIT), None);
+
+        let pillarboxed = Crop {
+            x: 4,
+            y: 0,
+            width: 32,
+            height: 30,
+        };
+        assert_eq!(
+            crop.union(&pillarboxed),
+            Crop {
+                x: 0,
+                y: 0,
+                width: 40,
+                height: 30
+            }
+        );
+        assert_eq!(
+            crop.scale(&Grid { x: 40, y: 30 }, &Grid { x: 80, y: 60 }),
+            Crop {
+                x: 0,
+                y: 10,
+                width: 80,
+                height: 40
+            }
+        );
+
+        let cropped = CroppedSource::new(&SolidSource, crop, Grid { x: 40, y: 30 });
+        let image = cropped.capture(100.0, 100, 50).unwrap();
+        assert_eq!(image.dimensions(), (100, 50));
+        let images = cropped.capture_all(&[1.0, 2.0], 30, 15).unwrap();
+        assert!(images.iter().all(|image| image.dimensions() == (30, 15)));
+    }
+
+    #[test]
+    fn image_sequences_are_found() {
+        let dir = std::env::temp_dir().join(format!("vcsr-sequence-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "shot_1001.png",
+            "shot_1002.png",
+            "shot_1003.png",
+            "shot_1005.png",
+            "matte_01.png",
+            "notes.txt",
+        ] {
+            fs::write(dir.join(name), b"image").unwrap();
+        }
+
+        let sequence = ImageSequence::find(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sequence.prefix, "shot_");
+        assert_eq!(sequence.first_frame, 1001);
+        assert_eq!(sequence.frame_count, 3);
+        assert_eq!(sequence.size_bytes, 15);
+        assert_eq!(sequence.pattern(), dir.join("shot_%d.png"));
+        assert!(sequence.contains(&dir.join("shot_1005.png")));
+        assert!(!sequence.contains(&dir.join("matte_01.png")));
+        assert_eq!(
+            option_args(&sequence.input_options(FrameRate::from_str("24000/1001").unwrap())),
+            vec!["-framerate", "24000/1001", "-start_number", "1001"]
+        );
+    }
+
+    #[test]
+    fn image_sequences_may_be_unpadded() {
+        let dir = std::env::temp_dir().join(format!("vcsr-unpadded-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for number in 1..=12 {
+            fs::write(dir.join(format!("f_{}.png", number)), b"image").unwrap();
+        }
+        fs::write(dir.join("f_07.png"), b"image").unwrap();
+
+        let sequence = ImageSequence::find(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(sequence.first_frame, 1);
+        assert_eq!(sequence.frame_count, 12);
+        assert_eq!(sequence.pattern(), dir.join("f_%d.png"));
+        assert!(sequence.contains(&dir.join("f_10.png")));
+        assert!(!sequence.contains(&dir.join("f_07.png")));
+    }
+
+    #[test]
+    fn capabilities_parse_filters() {
+        let output = "Filters:
+  T.. = Timeline support
+  ..C = Command support
+  A = Audio input/output
+  | = Source or sink filter
+ ... abench            A->A       Benchmark part of a filtergraph.
+ TSC scale             V->V       Scale the input video size and/or convert the image format.
+ ... concat            N->N       Concatenate audio and video streams.";
+        let capabilities = Capabilities {
+            version: String::from("6.0"),
+            filters: Capabilities::parse_filters(output),
+        };
+        assert_eq!(capabilities.filters, vec!["abench", "scale", "concat"]);
+        assert!(capabilities.require_filter("scale", "captures").is_ok());
+        assert!(capabilities
+            .require_filter("zscale", "tone mapping")
+            .is_err());
+    }
+
+    #[test]
+    fn nearest_keyframe_snaps_either_side() {
+        let keyframes = [0.0, 2.0, 4.5, 10.0];
+        assert_eq!(MediaCapture::nearest_keyframe(&keyframes, 0.9), Some(0.0));
+        assert_eq!(MediaCapture::nearest_keyframe(&keyframes, 1.5), Some(2.0));
+        assert_eq!(MediaCapture::nearest_keyframe(&keyframes, 4.5), Some(4.5));
+        assert_eq!(MediaCapture::nearest_keyframe(&keyframes, 30.0), Some(10.0));
+        assert_eq!(MediaCapture::nearest_keyframe(&[], 1.0), None);
+
+        assert_eq!(
+            MediaCapture::distinct_keyframes(&keyframes, &[1.5, 1.9, 2.1, 30.0, 31.0]),
+            vec![0.0, 2.0, 4.5, 10.0, 10.0]
+        );
+    }
+
+    #[test]
+    fn frame_source_capture_all_keeps_order() {
+        let images = SolidSource.capture_all(&[10.0, 20.0, 30.0], 4, 2).unwrap();
+        assert_eq!(images.len(), 3);
+        assert_eq!(images[1].dimensions(), (4, 2));
+        let avg_colours: Vec<f32> = images
+            .iter()
+            .map(MediaCapture::compute_avg_colour)
+            .collect();
+        assert_eq!(avg_colours, vec![10.0, 20.0, 30.0]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn split_frames_reports_failed_capture() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        let mut output = Output {
+            status: ExitStatus::from_raw(1 << 8),
+            stdout: vec![],
+            stderr: b"Invalid data found when processing input\n".to_vec(),
+        };
+        let err = MediaCapture::split_frames(output.clone(), "00:01:00", 2, 2, 1).unwrap_err();
+        assert!(matches!(
+            err,
+            VcsrError::CaptureFailed { exit_code: Some(1), ref stderr, .. }
+                if stderr == "Invalid data found when processing input"
+        ));
+
+        output.status = ExitStatus::from_raw(0);
+        output.stdout = vec![0; 16];
+        let images = MediaCapture::split_frames(output, "00:01:00", 2, 2, 1).unwrap();
+        assert_eq!(images.len(), 1);
+    }
+
+    #[test]
+    fn ffprobe_parses_leniently() {
+        let ffprobe: Ffprobe = serde_json::from_str(
+            r#"{
+                "streams": [
+                    {
+                        "index": 0,
+                        "codec_type": "video",
+                        "width": 1920,
+                        "height": 1080,
+                        "level": -99,
+                        "disposition": {"default": 1, "attached_pic": 0, "non_diegetic": 0},
+                        "tags": {"rotate": "90", "BPS": "4000000"},
+                        "side_data_list": [
+                            {"side_data_type": "Display Matrix", "rotation": -90}
+                        ],
+                        "film_grain": 0
+                    },
+                    {"index": 1, "codec_type": "attachment", "tags": {"filename": "font.ttf"}}
+                ],
+                "chapters": [
+                    {
+                        "id": 1,
+                        "time_base": "1/1000",
+                        "start": 0,
+                        "start_time": "0.000000",
+                        "end": 60000,
+                        "end_time": "60.000000",
+                        "tags": {"title": "Opening"}
+                    }
+                ],
+                "format": {"filename": "video.mkv", "duration": "60.0", "nb_stream_groups": 0}
+            }"#,
+        )
+        .unwrap();
+
+        let video = ffprobe.streams[0].stream();
+        assert_eq!(video.level, Some(-99));
+        assert_eq!(video.disposition.default, 1);
+        assert_eq!(video.tags.rotate, Some(90));
+        assert_eq!(video.tags.extra["BPS"], "4000000");
+        assert_eq!(video.side_data_list[0].rotation, Some(-90.0));
+        assert_eq!(video.extra["film_grain"], 0);
+        assert!(matches!(ffprobe.streams[1], Stream::AttachmentStream(_)));
+        assert_eq!(ffprobe.chapters[0].tags.title.as_deref(), Some("Opening"));
+        assert_eq!(ffprobe.format.extra["nb_stream_groups"], 0);
+        assert_eq!(ffprobe.format.size, None);
+    }
+
+    #[test]
+    fn chapters_are_sorted_and_looked_up() {
+        let ffprobe: Ffprobe = serde_json::from_str(
+            r#"{
+                "chapters": [
+                    {"id": 2, "start_time": "30.0", "end_time": "90.0", "tags": {"title": "Middle"}},
+                    {"id": 1, "start_time": "0.0", "end_time": "30.0", "tags": {"title": "Start"}},
+                    {"id": 3, "start_time": "90.0", "end_time": "90.0"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let chapters = MediaInfo::compute_chapters(&ffprobe);
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title.as_deref(), Some("Start"));
+        assert_eq!(
+            MediaInfo::chapter_at(&chapters, 30.0).and_then(|c| c.title.as_deref()),
+            Some("Middle")
+        );
+        assert_eq!(MediaInfo::chapter_at(&chapters, 95.0), None);
+    }
+
+    #[test]
+    fn find_video_stream_skips_attached_pictures() {
+        let ffprobe: Ffprobe = serde_json::from_str(
+            r#"{
+                "streams": [
+                    {"index": 0, "codec_type": "video", "disposition": {"attached_pic": 1}},
+                    {"index": 1, "codec_type": "audio"},
+                    {"index": 2, "codec_type": "video"},
+                    {"index": 3, "codec_type": "video"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let index = |video_stream| {
+            MediaInfo::find_video_stream(&ffprobe, video_stream).and_then(|s| s.stream().index)
+        };
+        assert_eq!(index(None), Some(2));
+        assert_eq!(index(Some(3)), Some(3));
+        assert_eq!(index(Some(0)), Some(0));
+        assert_eq!(index(Some(1)), None);
+    }
+
+    #[test]
+    fn hdr_is_tone_mapped() {
+        let hdr = |json: &str| {
+            let stream: StreamStruct = serde_json::from_str(json).unwrap();
+            MediaInfo::is_hdr(&stream)
+        };
+        assert!(hdr(r#"{"color_transfer": "smpte2084"}"#));
+        assert!(hdr(r#"{"color_transfer": "arib-std-b67"}"#));
+        assert!(!hdr(r#"{"color_transfer": "bt709"}"#));
+        assert!(!hdr(r#"{}"#));
+
+        assert_eq!(Tonemap::Off.filter(), None);
+        assert!(Tonemap::Mobius
+            .filter()
+            .unwrap()
+            .contains("tonemap=tonemap=mobius"));
+    }
+
+    #[test]
+    fn interlacing_follows_field_order() {
+        let interlaced = |json: &str| {
+            let stream: StreamStruct = serde_json::from_str(json).unwrap();
+            MediaInfo::is_interlaced(&stream)
+        };
+        assert!(interlaced(r#"{"field_order": "tt"}"#));
+        assert!(interlaced(r#"{"field_order": "bt"}"#));
+        assert!(!interlaced(r#"{"field_order": "progressive"}"#));
+        assert!(!interlaced(r#"{"field_order": "unknown"}"#));
+        assert!(!interlaced(r#"{}"#));
+
+        assert!(Deinterlace::Auto.applies(true));
+        assert!(!Deinterlace::Auto.applies(false));
+        assert!(Deinterlace::On.applies(false));
+        assert!(!Deinterlace::Off.applies(true));
+    }
+
+    #[test]
+    fn rotation_from_tags_and_display_matrix() {
+        let rotation = |json: &str| {
+            let stream: StreamStruct = serde_json::from_str(json).unwrap();
+            MediaInfo::compute_rotation(&stream)
+        };
+        assert_eq!(rotation(r#"{}"#), 0);
+        assert_eq!(rotation(r#"{"tags": {"rotate": "90"}}"#), 90);
+        assert_eq!(rotation(r#"{"tags": {"rotate": "-90"}}"#), 270);
+        assert_eq!(
+            rotation(
+                r#"{"side_data_list": [{"side_data_type": "Display Matrix", "rotation": -90}]}"#
+            ),
+            90
+        );
+        assert_eq!(
+            rotation(
+                r#"{"side_data_list": [{"side_data_type": "Display Matrix", "rotation": 90.00}]}"#
+            ),
+            270
+        );
+        assert_eq!(
+            rotation(
+                r#"{"tags": {"rotate": "90"}, "side_data_list": [{"side_data_type": "Display Matrix", "rotation": 180}]}"#
+            ),
+            180
+        );
+    }
+
+    #[test]
+    fn tracks_describe_audio_and_subtitles() {
+        let ffprobe: Ffprobe = serde_json::from_str(
+            r#"{
+                "streams": [
+                    {"index": 0, "codec_type": "video"},
+                    {
+                        "index": 1,
+                        "codec_type": "audio",
+                        "codec_name": "ac3",
+                        "channels": 6,
+                        "channel_layout": "5.1(side)",
+                        "sample_rate": "48000",
+                        "tags": {"language": "eng", "BPS": "448000"}
+                    },
+                    {
+                        "index": 2,
+                        "codec_type": "subtitle",
+                        "codec_name": "subrip",
+                        "disposition": {"forced": 1},
+                        "tags": {"language": "fre"}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let (audio_tracks, subtitle_tracks) = MediaInfo::compute_tracks(&ffprobe);
+        assert_eq!(
+            audio_tracks[0].to_string(),
+            "Audio #1: ac3, 5.1(side) (6 channels), 48000 Hz, 448 kb/s, eng"
+        );
+        assert_eq!(
+            subtitle_tracks[0].to_string(),
+            "Subtitles #2: subrip, fre, forced"
+        );
+    }
+
+    #[test]
+    fn subtitle_sources_are_found() {
+        let dir = std::env::temp_dir().join(format!("vcsr-subtitles-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let video = dir.join("movie.mkv");
+        fs::write(&video, b"video").unwrap();
+        fs::write(dir.join("movie.en.srt"), b"").unwrap();
+        fs::write(dir.join("other.srt"), b"").unwrap();
+        let ffprobe: Ffprobe = serde_json::from_str(
+            r#"{
+                "streams": [
+                    {"index": 0, "codec_type": "video"},
+                    {"index": 1, "codec_type": "subtitle", "codec_name": "hdmv_pgs_subtitle"},
+                    {"index": 2, "codec_type": "subtitle", "codec_name": "subrip"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            MediaInfo::find_subtitle_source(&video, &ffprobe, None, None),
+            Some(SubtitleSource::File(dir.join("movie.en.srt")))
+        );
+        assert_eq!(
+            MediaInfo::find_subtitle_source(&video, &ffprobe, None, Some(2)),
+            Some(SubtitleSource::Stream(2))
+        );
+        assert_eq!(
+            MediaInfo::find_subtitle_source(&video, &ffprobe, None, Some(1)),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_srt_cleans_cues() {
+        let srt = "1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\n{\\an8}there\r\n\r\n2\n00:01:00,000 --> 00:01:03,000\nSecond\\Ncue\n";
+        assert_eq!(
+            parse_srt(srt),
+            vec![
+                SubtitleCue {
+                    start: 1.0,
+                    end: 2.5,
+                    text: String::from("Hello\nthere"),
+                },
+                SubtitleCue {
+                    start: 60.0,
+                    end: 63.0,
+                    text: String::from("Second\ncue"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn frame_rates_stay_exact() {
+        let ntsc_film = FrameRate::from_str("24000/1001").unwrap();
+        assert_eq!(ntsc_film.to_string(), "23.976");
+        assert_eq!(ntsc_film.frame_number(1001.0 / 24000.0 * 48.0), 48);
+        assert_eq!(ntsc_film.timecode(60.0), "00:00:59:22");
+        assert_eq!(FrameRate::from_str("25").unwrap().to_string(), "25");
+        assert!(FrameRate::from_str("0/0").is_err());
+
+        let ntsc = FrameRate::from_str("30000/1001").unwrap();
+        assert_eq!(ntsc.to_string(), "29.97");
+        assert_eq!(ntsc.timecode(1800.0 * 1001.0 / 30000.0), "00:01:00;02");
+        assert_eq!(ntsc.timecode(600.0), "00:10:00;00");
+        assert!(ntsc.approx_eq(&FrameRate::from_str("2997/100").unwrap()));
+        assert!(!ntsc.approx_eq(&FrameRate::from_str("60").unwrap()));
+
+        let rate = |rate: &str| FrameRate::from_str(rate).unwrap();
+        assert!(!FrameRate::is_variable(&rate("25/1"), &rate("50/1")));
+        assert!(!FrameRate::is_variable(&ntsc, &rate("30000/1001")));
+        assert!(FrameRate::is_variable(&rate("29/1"), &rate("30/1")));
+        assert!(FrameRate::is_variable(&rate("40/1"), &rate("60/1")));
+    }
+
+    #[test]
+    fn durations_are_checked_and_recovered() {
+        let attributes = |json: &str| {
+            let ffprobe: Ffprobe = serde_json::from_str(json).unwrap();
+            MediaInfo::create_media_attributes(&ffprobe, None).unwrap()
+        };
+        let stream = attributes(
+            r#"{
+                "streams": [{"index": 0, "codec_type": "video", "width": 640, "height": 360, "duration": "60.0"}],
+                "format": {"filename": "video.mkv", "duration": "61.0", "size": "1000"}
+            }"#,
+        );
+        assert_eq!(stream.duration_seconds, 60.0);
+        assert_eq!(stream.duration_source, Some(DurationSource::Stream));
+
+        let container = attributes(
+            r#"{
+                "streams": [{"index": 0, "codec_type": "video", "width": 640, "height": 360}],
+                "format": {"filename": "video.mkv", "duration": "61.0", "size": "1000"}
+            }"#,
+        );
+        assert_eq!(container.duration_source, Some(DurationSource::Container));
+
+        let bogus = attributes(
+            r#"{
+                "streams": [{"index": 0, "codec_type": "video", "width": 640, "height": 360, "duration": "60.0"}],
+                "format": {"filename": "video.ts", "duration": "95000.0", "size": "1000"}
+            }"#,
+        );
+        assert_eq!(bogus.duration_source, Some(DurationSource::Stream));
+        assert!(bogus.duration_doubtful);
+
+        let mut missing = attributes(
+            r#"{
+                "streams": [{"index": 0, "codec_type": "video", "width": 640, "height": 360}],
+                "format": {"filename": "video.ts", "format_name": "mpegts", "size": "1000"}
+            }"#,
+        );
+        assert_eq!(missing.duration_source, None);
+        assert!(!missing.duration_doubtful);
+        let audio = attributes(
+            r#"{
+                "streams": [{"index": 0, "codec_type": "audio", "codec_name": "mp3"}],
+                "format": {"filename": "audio.mp3", "format_name": "mp3", "size": "1000"}
+            }"#,
+        );
+        assert_eq!(audio.audio_stream, Some(0));
+        assert_eq!(audio.duration_source, None);
+        let span = packet_span(vec![(1.4, 1.44), (61.36, 61.4), (30.0, 30.04)].into_iter());
+        missing.set_duration(span.unwrap(), DurationSource::Packets);
+        assert!((missing.duration_seconds - 60.0).abs() < 1e-3);
+        assert_eq!(packet_span(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn start_time_comes_from_the_container() {
+        let attributes = |format: &str| {
+            let json = format!(
+                r#"{{
+                    "streams": [{{"index": 0, "codec_type": "video", "width": 640, "height": 360, "duration": "60.0"}}],
+                    "format": {}
+                }}"#,
+                format
+            );
+            let ffprobe: Ffprobe = serde_json::from_str(&json).unwrap();
+            MediaInfo::create_media_attributes(&ffprobe, None).unwrap()
+        };
+        let offset =
+            attributes(r#"{"filename": "video.ts", "start_time": "1.400000", "size": "1"}"#);
+        assert_eq!(offset.start_time, 1.4);
+        let missing = attributes(r#"{"filename": "video.mp4", "start_time": "N/A", "size": "1"}"#);
+        assert_eq!(missing.start_time, 0.0);
+    }
+
+    #[test]
+    fn presentation_labels_add_the_start_time() {
+        assert_eq!(TimestampBase::Media.label(12.5, 95000.27), "00:12.50");
+        assert_eq!(
+            TimestampBase::Presentation.label(12.5, 95000.27),
+            "26:23:32.77"
+        );
+        assert_eq!(TimestampBase::Presentation.label(12.5, 0.0), "00:12.50");
+        assert_eq!(MediaInfo::pretty_time(95000.27, true), "26:23:20.270");
+    }
 }
@@ -0,0 +1,735 @@
+//! Probing and capture through the FFmpeg shared libraries, used instead of
+//! the `ffmpeg` and `ffprobe` executables when built with the `libav`
+//! feature.
+use crate::errors::VcsrError;
+use crate::models::{clean_cue_text, packet_span, MediaInfo, SubtitleCue};
+use crate::process;
+
+use ffmpeg::{
+    codec::{self, decoder, packet::side_data, subtitle::Rect},
+    filter,
+    format::{self, sample, stream::Disposition, Pixel, Sample, Stream},
+    media::Type,
+    software::scaling::{context::Context as Scaler, flag::Flags},
+    util::{
+        frame::{audio::Audio, video::Video},
+        picture,
+    },
+    ChannelLayout, Dictionary, DictionaryRef, FieldOrder, Rational,
+};
+use ffmpeg_next as ffmpeg;
+use image::{imageops, RgbaImage};
+use serde_json::{json, Map, Value};
+use std::{fs, path::Path};
+
+/// Units per second of container level timestamps (`AV_TIME_BASE`).
+const TIME_BASE: f64 = 1_000_000.0;
+
+/// Open `path` with the demuxer `options`, such as the frame rate of an
+/// image sequence.
+fn open_input<P: AsRef<Path> + ?Sized>(
+    path: &P,
+    options: &[(String, String)],
+) -> Result<format::context::Input, VcsrError> {
+    let mut dictionary = Dictionary::new();
+    for (key, value) in options {
+        dictionary.set(key, value);
+    }
+    Ok(format::input_with_dictionary(path, dictionary)?)
+}
+
+/// Probe `path`, opened with the demuxer `options`, and build the same
+/// JSON ffprobe would output.
+pub fn probe_json(path: &Path, options: &[(String, String)]) -> Result<String, VcsrError> {
+    ffmpeg::init()?;
+    let ictx = open_input(path, options)?;
+
+    let mut streams = vec![];
+    for stream in ictx.streams() {
+        let parameters = stream.parameters();
+        let medium = parameters.medium();
+        let codec_type = match medium {
+            Type::Video => "video",
+            Type::Audio => "audio",
+            Type::Subtitle => "subtitle",
+            Type::Data => "data",
+            _ => continue,
+        };
+
+        let mut value = json!({
+            "codec_type": codec_type,
+            "codec_name": parameters.id().name(),
+            "index": stream.index(),
+            "avg_frame_rate": rational_string(stream.avg_frame_rate()),
+            "r_frame_rate": rational_string(stream.rate()),
+            "time_base": rational_string(stream.time_base()),
+            "disposition": disposition(stream.disposition()),
+            "tags": dictionary(stream.metadata()),
+        });
+        if stream.duration() > 0 {
+            value["duration"] = json!(format!(
+                "{:.6}",
+                stream.duration() as f64 * f64::from(stream.time_base())
+            ));
+        }
+
+        let decoder = codec::context::Context::from_parameters(parameters)?.decoder();
+        match medium {
+            Type::Video => {
+                let video = decoder.video()?;
+                value["width"] = json!(video.width());
+                value["height"] = json!(video.height());
+                value["color_transfer"] = json!(video.color_transfer_characteristic().name());
+                value["field_order"] = json!(field_order(&video));
+                if let Some(rotation) = display_rotation(&stream) {
+                    value["side_data_list"] = json!([{
+                        "side_data_type": "Display Matrix",
+                        "rotation": rotation,
+                    }]);
+                }
+                let aspect_ratio = video.aspect_ratio();
+                if aspect_ratio.numerator() > 0 {
+                    value["sample_aspect_ratio"] = json!(format!(
+                        "{}:{}",
+                        aspect_ratio.numerator(),
+                        aspect_ratio.denominator()
+                    ));
+                }
+            }
+            Type::Audio => {
+                let audio = decoder.audio()?;
+                value["sample_rate"] = json!(audio.rate().to_string());
+                value["channels"] = json!(audio.channels());
+                if audio.bit_rate() > 0 {
+                    value["bit_rate"] = json!(audio.bit_rate().to_string());
+                }
+            }
+            _ => {}
+        }
+        streams.push(value);
+    }
+
+    let chapters: Vec<Value> = ictx
+        .chapters()
+        .map(|chapter| {
+            let time_base = f64::from(chapter.time_base());
+            json!({
+                "id": chapter.id(),
+                "time_base": rational_string(chapter.time_base()),
+                "start": chapter.start(),
+                "start_time": format!("{:.6}", chapter.start() as f64 * time_base),
+                "end": chapter.end(),
+                "end_time": format!("{:.6}", chapter.end() as f64 * time_base),
+                "tags": dictionary(chapter.metadata()),
+            })
+        })
+        .collect();
+    let start_time = start_time(&ictx);
+
+    let ffprobe = json!({
+        "streams": streams,
+        "chapters": chapters,
+        "format": {
+            "filename": path.to_string_lossy(),
+            "nb_streams": ictx.nb_streams(),
+            "nb_programs": 0,
+            "format_name": ictx.format().name(),
+            "format_long_name": ictx.format().description(),
+            "start_time": format!("{:.6}", start_time),
+            "duration": format!("{:.6}", ictx.duration() as f64 / TIME_BASE),
+            "size": fs::metadata(path)?.len().to_string(),
+            "bit_rate": ictx.bit_rate().to_string(),
+            "probe_score": 100,
+            "tags": dictionary(ictx.metadata()),
+        },
+    });
+    Ok(ffprobe.to_string())
+}
+
+/// Decode a frame at each of `times` seconds from stream `stream_index` of
+/// `path` opened with the demuxer `options`, passed through the `filters`
+/// chain if any, scaled to `width` x `height` and turned `rotation` degrees
+/// clockwise. The file is opened once and seeked for every capture;
+/// decoding always continues from the preceding keyframe up to the
+/// requested time, so captures are accurate.
+#[allow(clippy::too_many_arguments)]
+pub fn capture_frames(
+    path: &str,
+    options: &[(String, String)],
+    stream_index: usize,
+    times: &[f32],
+    width: u64,
+    height: u64,
+    frame_type: Option<&str>,
+    filters: Option<&str>,
+    rotation: u32,
+) -> Result<Vec<RgbaImage>, VcsrError> {
+    // Decoded frames are not rotated, so scale them to the size that
+    // becomes `width` x `height` once turned.
+    let (scaled_width, scaled_height) = if rotation % 180 == 90 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    ffmpeg::init()?;
+    let mut ictx = open_input(path, options)?;
+    let stream = video_stream(&ictx, stream_index)?;
+    let time_base = f64::from(stream.time_base());
+    let start_time = start_time(&ictx);
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+    // Filters may change the pixel format, so the scaler is created for
+    // the first frame that comes out of them.
+    let mut scaler: Option<Scaler> = None;
+    // The filter graph is also built for the first frame, and reused.
+    let mut frame_filter: Option<FrameFilter> = None;
+
+    let mut images = vec![];
+    for time in times {
+        if process::is_cancelled() {
+            return Err(VcsrError::Cancelled);
+        }
+        let target = ((*time as f64 + start_time) * TIME_BASE) as i64;
+        ictx.seek(target, ..target)?;
+        decoder.flush();
+
+        let mut decoded = Video::empty();
+        let receive = |decoder: &mut decoder::Video, decoded: &mut Video| {
+            while decoder.receive_frame(decoded).is_ok() {
+                // Frames without a timestamp cannot be placed, so are
+                // skipped rather than taken to be at the start.
+                let frame_time = match decoded.timestamp() {
+                    Some(timestamp) => timestamp as f64 * time_base - start_time,
+                    None => continue,
+                };
+                // Allow for the millisecond rounding of requested times so
+                // a time snapped to a keyframe still selects that keyframe.
+                if frame_time + 0.0005 >= *time as f64 && matches_frame_type(decoded, frame_type) {
+                    return true;
+                }
+            }
+            false
+        };
+        let mut found = false;
+        for (stream, packet) in ictx.packets() {
+            if stream.index() != stream_index {
+                continue;
+            }
+            decoder.send_packet(&packet)?;
+            if receive(&mut decoder, &mut decoded) {
+                found = true;
+                break;
+            }
+        }
+        // Decoders hold back the last few frames until the end of the
+        // stream, so drain them for times near the end.
+        if !found {
+            decoder.send_eof()?;
+            found = receive(&mut decoder, &mut decoded);
+        }
+        if !found {
+            return Err(VcsrError::CaptureFailed {
+                timestamp: MediaInfo::pretty_duration(*time, false, true),
+                exit_code: None,
+                stderr: String::from("no frame decoded at or after this time"),
+            });
+        }
+
+        if let Some(filters) = filters {
+            let frame_filter = match &mut frame_filter {
+                Some(frame_filter) => frame_filter,
+                None => frame_filter.insert(FrameFilter::new(&decoded, filters)?),
+            };
+            decoded = frame_filter.filter(&decoded)?;
+        }
+        let scaler = match &mut scaler {
+            Some(scaler) => scaler,
+            None => scaler.insert(Scaler::get(
+                decoded.format(),
+                decoded.width(),
+                decoded.height(),
+                Pixel::RGBA,
+                scaled_width as u32,
+                scaled_height as u32,
+                Flags::BILINEAR,
+            )?),
+        };
+        let mut rgba = Video::empty();
+        scaler.run(&decoded, &mut rgba)?;
+        let image = to_rgba_image(&rgba, scaled_width, scaled_height);
+        images.push(match rotation {
+            90 => imageops::rotate90(&image),
+            180 => imageops::rotate180(&image),
+            270 => imageops::rotate270(&image),
+            _ => image,
+        });
+    }
+    Ok(images)
+}
+
+/// The field order of `video` as ffprobe names it.
+fn field_order(video: &decoder::Video) -> &'static str {
+    // ffmpeg-next has no getter for the decoder's field order.
+    let field_order = FieldOrder::from(unsafe { (*video.as_ptr()).field_order });
+    match field_order {
+        FieldOrder::Progressive => "progressive",
+        FieldOrder::TT => "tt",
+        FieldOrder::BB => "bb",
+        FieldOrder::TB => "tb",
+        FieldOrder::BT => "bt",
+        FieldOrder::Unknown => "unknown",
+    }
+}
+
+/// Versions of the FFmpeg libraries vcsr is linked with.
+pub fn version() -> String {
+    format!(
+        "avformat {} avcodec {} avfilter {} swscale {}",
+        format::version(),
+        codec::version(),
+        filter::version(),
+        ffmpeg::software::scaling::version()
+    )
+}
+
+/// Whether the FFmpeg libraries have the filter `name`.
+pub fn has_filter(name: &str) -> bool {
+    ffmpeg::init().is_ok() && filter::find(name).is_some()
+}
+
+/// A filter chain, as given to ffmpeg's `-vf`, that captures are run
+/// through one at a time. The graph is built once, for frames like the
+/// first one filtered.
+struct FrameFilter {
+    graph: filter::Graph,
+    /// Whether the chain holds frames back to look at their neighbours,
+    /// as deinterlacers do.
+    temporal: bool,
+    pts: i64,
+}
+
+impl FrameFilter {
+    fn new(frame: &Video, spec: &str) -> Result<FrameFilter, VcsrError> {
+        let mut graph = filter::Graph::new();
+        let buffer = filter::find("buffer").ok_or(ffmpeg::Error::FilterNotFound)?;
+        let buffersink = filter::find("buffersink").ok_or(ffmpeg::Error::FilterNotFound)?;
+        let args = format!(
+            "video_size={}x{}:pix_fmt={}:time_base=1/1:pixel_aspect=1/1",
+            frame.width(),
+            frame.height(),
+            ffmpeg::ffi::AVPixelFormat::from(frame.format()) as i32
+        );
+        graph.add(&buffer, "in", &args)?;
+        graph.add(&buffersink, "out", "")?;
+        graph.output("in", 0)?.input("out", 0)?.parse(spec)?;
+        graph.validate()?;
+        Ok(FrameFilter {
+            graph,
+            temporal: spec
+                .split(',')
+                .any(|filter| filter.starts_with("yadif") || filter.starts_with("bwdif")),
+            pts: 0,
+        })
+    }
+
+    /// Run `frame` through the chain.
+    fn filter(&mut self, frame: &Video) -> Result<Video, VcsrError> {
+        // A deinterlacer outputs each frame once it has the next, and
+        // keeps the one before. Sending the frame three times pushes out
+        // what is left of the previous capture and makes the frame its own
+        // neighbour on both sides.
+        let copies = if self.temporal { 3 } else { 1 };
+        for _ in 0..copies {
+            let mut copy = frame.clone();
+            copy.set_pts(Some(self.pts));
+            self.pts += 1;
+            self.graph
+                .get("in")
+                .ok_or(ffmpeg::Error::FilterNotFound)?
+                .source()
+                .add(&copy)?;
+        }
+        let mut sink = self.graph.get("out").ok_or(ffmpeg::Error::FilterNotFound)?;
+        let mut filtered = Video::empty();
+        let mut last = None;
+        while sink.sink().frame(&mut filtered).is_ok() {
+            last = Some(filtered.clone());
+        }
+        Ok(last.ok_or(ffmpeg::Error::Other {
+            errno: ffmpeg::error::EAGAIN,
+        })?)
+    }
+}
+
+/// Times of every keyframe in stream `stream_index` of `path` opened with
+/// the demuxer `options`, read from packet flags without decoding.
+pub fn keyframe_times(
+    path: &str,
+    options: &[(String, String)],
+    stream_index: usize,
+) -> Result<Vec<f32>, VcsrError> {
+    ffmpeg::init()?;
+    let mut ictx = open_input(path, options)?;
+    let stream = video_stream(&ictx, stream_index)?;
+    let time_base = f64::from(stream.time_base());
+    let start_time = start_time(&ictx);
+
+    let mut keyframes: Vec<f32> = ictx
+        .packets()
+        .filter(|(stream, packet)| stream.index() == stream_index && packet.is_key())
+        .filter_map(|(_, packet)| packet.pts())
+        .map(|pts| (pts as f64 * time_base - start_time) as f32)
+        .collect();
+    keyframes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    keyframes.dedup();
+    Ok(keyframes)
+}
+
+/// Seconds from the first packet of stream `stream_index` of `path` to
+/// the end of its last, for files whose probed duration is missing or
+/// doubtful.
+pub fn packet_duration(
+    path: &str,
+    options: &[(String, String)],
+    stream_index: usize,
+) -> Result<f32, VcsrError> {
+    ffmpeg::init()?;
+    let mut ictx = open_input(path, options)?;
+    let stream = ictx.stream(stream_index).ok_or(VcsrError::MediaError)?;
+    let time_base = f64::from(stream.time_base());
+
+    let packets = ictx
+        .packets()
+        .filter(|(stream, _)| stream.index() == stream_index)
+        .filter_map(|(_, packet)| {
+            let pts = packet.pts()?;
+            let start = (pts as f64 * time_base) as f32;
+            let end = ((pts + packet.duration()) as f64 * time_base) as f32;
+            Some((start, end))
+        });
+    packet_span(packets).ok_or(VcsrError::MediaError)
+}
+
+/// Times of frames in stream `stream_index` of `path`, opened with the
+/// demuxer `options`, whose luma histogram differs from the previous
+/// frame's by more than `threshold`, as a fraction of the pixels that moved
+/// between histogram bins.
+pub fn scene_changes(
+    path: &str,
+    options: &[(String, String)],
+    stream_index: usize,
+    threshold: f32,
+) -> Result<Vec<f32>, VcsrError> {
+    const SIZE: u32 = 64;
+    const BINS: usize = 64;
+
+    ffmpeg::init()?;
+    let mut ictx = open_input(path, options)?;
+    let stream = video_stream(&ictx, stream_index)?;
+    let time_base = f64::from(stream.time_base());
+    let start_time = start_time(&ictx);
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+    let mut scaler = Scaler::get(
+        decoder.format(),
+        decoder.width(),
+        decoder.height(),
+        Pixel::GRAY8,
+        SIZE,
+        SIZE,
+        Flags::FAST_BILINEAR,
+    )?;
+
+    let mut scenes = vec![];
+    let mut previous: Option<[f32; BINS]> = None;
+    let mut decoded = Video::empty();
+    let mut grey = Video::empty();
+    let mut score_frames = |decoder: &mut ffmpeg::decoder::Video| -> Result<(), VcsrError> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            scaler.run(&decoded, &mut grey)?;
+            let mut histogram = [0.0; BINS];
+            let stride = grey.stride(0);
+            for row in grey.data(0).chunks(stride).take(SIZE as usize) {
+                for luma in &row[..SIZE as usize] {
+                    histogram[*luma as usize * BINS / 256] += 1.0 / (SIZE * SIZE) as f32;
+                }
+            }
+            if let Some(previous) = previous {
+                let difference: f32 = previous
+                    .iter()
+                    .zip(histogram.iter())
+                    .map(|(a, b)| (a - b).abs())
+                    .sum::<f32>()
+                    / 2.0;
+                if difference > threshold {
+                    let pts = decoded.timestamp().unwrap_or(0) as f64 * time_base;
+                    scenes.push((pts - start_time) as f32);
+                }
+            }
+            previous = Some(histogram);
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if process::is_cancelled() {
+            return Err(VcsrError::Cancelled);
+        }
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            score_frames(&mut decoder)?;
+        }
+    }
+    decoder.send_eof()?;
+    score_frames(&mut decoder)?;
+
+    scenes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    scenes.dedup();
+    Ok(scenes)
+}
+
+/// Decode `seconds` of audio stream `stream_index` of `path` from `start`,
+/// mixed down to mono 16 bit samples at `rate`.
+pub fn audio_samples(
+    path: &str,
+    options: &[(String, String)],
+    stream_index: usize,
+    rate: u32,
+    start: f32,
+    seconds: f32,
+) -> Result<Vec<i16>, VcsrError> {
+    const FORMAT: Sample = Sample::I16(sample::Type::Packed);
+
+    ffmpeg::init()?;
+    let mut ictx = open_input(path, options)?;
+    let stream = ictx
+        .stream(stream_index)
+        .filter(|stream| stream.parameters().medium() == Type::Audio)
+        .ok_or_else(|| {
+            VcsrError::AudioError(format!("{} has no audio stream {}", path, stream_index))
+        })?;
+    let time_base = f64::from(stream.time_base());
+    let start_time = start_time(&ictx);
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .audio()?;
+    let mut resampler = decoder.resampler(FORMAT, ChannelLayout::MONO, rate)?;
+
+    let target = ((start as f64 + start_time) * TIME_BASE) as i64;
+    ictx.seek(target, ..target)?;
+    let end = (start + seconds) as f64;
+
+    // Time of the first sample decoded, which seeking puts at or before
+    // `start`.
+    let mut first: Option<f64> = None;
+    let mut samples = vec![];
+    let mut decoded = Audio::empty();
+    let mut finished = false;
+    for (stream, packet) in ictx.packets() {
+        if process::is_cancelled() {
+            return Err(VcsrError::Cancelled);
+        }
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let time = decoded.pts().map(|pts| pts as f64 * time_base - start_time);
+            if first.is_none() {
+                first = Some(time.unwrap_or(start as f64));
+            }
+            let mut resampled = Audio::empty();
+            resampler.run(&decoded, &mut resampled)?;
+            samples.extend_from_slice(resampled.plane::<i16>(0));
+            if time.is_some_and(|time| time >= end) {
+                finished = true;
+            }
+        }
+        if finished {
+            break;
+        }
+    }
+    // Frames held back by the decoder at the end of the file.
+    if !finished {
+        decoder.send_eof()?;
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            if first.is_none() {
+                first = Some(start as f64);
+            }
+            let mut resampled = Audio::empty();
+            resampler.run(&decoded, &mut resampled)?;
+            samples.extend_from_slice(resampled.plane::<i16>(0));
+        }
+    }
+
+    let skip = first.map_or(0.0, |first| (start as f64 - first).max(0.0) * rate as f64) as usize;
+    let count = (seconds * rate as f32) as usize;
+    Ok(samples.into_iter().skip(skip).take(count).collect())
+}
+
+/// Seconds the earliest stream of `ictx` starts at, which ffprobe reports
+/// as the start time of the container. Like the ffmpeg CLI, times given
+/// to and returned from this module are counted from it.
+fn start_time(ictx: &format::context::Input) -> f64 {
+    let start_time = ictx
+        .streams()
+        .filter(|stream| stream.start_time() >= 0)
+        .map(|stream| stream.start_time() as f64 * f64::from(stream.time_base()))
+        .fold(f64::INFINITY, f64::min);
+    if start_time.is_finite() {
+        start_time
+    } else {
+        0.0
+    }
+}
+
+/// Stream `stream_index` of `ictx`, which must be a video stream.
+fn video_stream(
+    ictx: &format::context::Input,
+    stream_index: usize,
+) -> Result<Stream<'_>, VcsrError> {
+    ictx.stream(stream_index)
+        .filter(|stream| stream.parameters().medium() == Type::Video)
+        .ok_or(VcsrError::VideoStreamError)
+}
+
+/// Counter-clockwise rotation of the display matrix in `stream`'s side data,
+/// reported the same way as ffprobe and `av_display_rotation_get`.
+fn display_rotation(stream: &Stream) -> Option<f64> {
+    let side_data = stream
+        .side_data()
+        .find(|side_data| side_data.kind() == side_data::Type::DisplayMatrix)?;
+    let matrix: Vec<f64> = side_data
+        .data()
+        .chunks_exact(4)
+        .map(|bytes| i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64)
+        .collect();
+    if matrix.len() < 9 {
+        return None;
+    }
+    let scale_x = matrix[0].hypot(matrix[3]);
+    let scale_y = matrix[1].hypot(matrix[4]);
+    if scale_x == 0.0 || scale_y == 0.0 {
+        return None;
+    }
+    let rotation = (matrix[1] / scale_y)
+        .atan2(matrix[0] / scale_x)
+        .to_degrees();
+    Some(-rotation)
+}
+
+fn dictionary(dictionary: DictionaryRef) -> Map<String, Value> {
+    dictionary
+        .iter()
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect()
+}
+
+/// The disposition flags ffprobe reports, as 0 or 1.
+fn disposition(disposition: Disposition) -> Value {
+    let flag = |flag: Disposition| u32::from(disposition.contains(flag));
+    json!({
+        "attached_pic": flag(Disposition::ATTACHED_PIC),
+        "captions": flag(Disposition::CAPTIONS),
+        "clean_effects": flag(Disposition::CLEAN_EFFECTS),
+        "comment": flag(Disposition::COMMENT),
+        "default": flag(Disposition::DEFAULT),
+        "descriptions": flag(Disposition::DESCRIPTIONS),
+        "dub": flag(Disposition::DUB),
+        "forced": flag(Disposition::FORCED),
+        "hearing_impaired": flag(Disposition::HEARING_IMPAIRED),
+        "karaoke": flag(Disposition::KARAOKE),
+        "lyrics": flag(Disposition::LYRICS),
+        "metadata": flag(Disposition::METADATA),
+        "original": flag(Disposition::ORIGINAL),
+        "visual_impaired": flag(Disposition::VISUAL_IMPAIRED),
+    })
+}
+
+/// Cues of subtitle stream `stream_index` of `path`, or of its first
+/// subtitle stream when no index is given, as for a subtitle file.
+pub fn subtitle_cues(
+    path: &str,
+    stream_index: Option<usize>,
+) -> Result<Vec<SubtitleCue>, VcsrError> {
+    ffmpeg::init()?;
+    let mut ictx = format::input(&path)?;
+    let stream = match stream_index {
+        Some(index) => ictx.stream(index),
+        None => ictx.streams().best(Type::Subtitle),
+    }
+    .filter(|stream| stream.parameters().medium() == Type::Subtitle)
+    .ok_or_else(|| VcsrError::SubtitleError(format!("{} has no subtitle stream", path)))?;
+    let stream_index = stream.index();
+    let time_base = f64::from(stream.time_base());
+    let start_time = start_time(&ictx);
+    let mut decoder = codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .subtitle()?;
+
+    let mut cues = vec![];
+    let mut subtitle = codec::subtitle::Subtitle::new();
+    for (stream, packet) in ictx.packets() {
+        if process::is_cancelled() {
+            return Err(VcsrError::Cancelled);
+        }
+        if stream.index() != stream_index || !decoder.decode(&packet, &mut subtitle)? {
+            continue;
+        }
+        let pts = packet.pts().unwrap_or(0) as f64 * time_base - start_time;
+        let start = pts + f64::from(subtitle.start()) / 1000.0;
+        let end = if subtitle.end() > subtitle.start() && subtitle.end() != u32::MAX {
+            pts + f64::from(subtitle.end()) / 1000.0
+        } else {
+            pts + packet.duration() as f64 * time_base
+        };
+        let text: Vec<String> = subtitle
+            .rects()
+            .filter_map(|rect| match rect {
+                Rect::Text(text) => Some(text.get().to_string()),
+                // ASS events carry eight fields before the text itself.
+                Rect::Ass(ass) => ass.get().splitn(9, ',').nth(8).map(String::from),
+                _ => None,
+            })
+            .collect();
+        let text = clean_cue_text(&text.join("\n"));
+        if !text.is_empty() {
+            cues.push(SubtitleCue {
+                start: start as f32,
+                end: end as f32,
+                text,
+            });
+        }
+    }
+    cues.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    Ok(cues)
+}
+
+fn rational_string(rational: Rational) -> String {
+    format!("{}/{}", rational.numerator(), rational.denominator())
+}
+
+/// Whether `frame` satisfies the `--frame-type` restriction.
+fn matches_frame_type(frame: &Video, frame_type: Option<&str>) -> bool {
+    match frame_type {
+        None => true,
+        Some("key") => frame.is_key(),
+        Some("I") => frame.kind() == picture::Type::I,
+        Some("P") => frame.kind() == picture::Type::P,
+        Some("B") => frame.kind() == picture::Type::B,
+        Some(_) => true,
+    }
+}
+
+/// Copy a scaled RGBA frame into an image, dropping any row padding.
+fn to_rgba_image(frame: &Video, width: u64, height: u64) -> RgbaImage {
+    let stride = frame.stride(0);
+    let row_size = width as usize * 4;
+    let mut raw = Vec::with_capacity(row_size * height as usize);
+    for row in frame.data(0).chunks(stride).take(height as usize) {
+        raw.extend_from_slice(&row[..row_size]);
+    }
+    RgbaImage::from_raw(width as u32, height as u32, raw).unwrap()
+}
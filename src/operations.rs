@@ -2,17 +2,19 @@ use crate::args::Args;
 use crate::constants::*;
 use crate::errors::VcsrError;
 use crate::models::{
-    Dimensions, Frame, Grid, MediaAttributes, MediaCapture, MediaInfo, MetadataPosition,
-    TimestampPosition,
+    CaptureMode, ChapterMarker, Crop, Dimensions, DurationSource, Frame, FrameSource, Grid,
+    MediaAttributes, MediaCapture, MediaInfo, MetadataPosition, SubtitleCue, TimestampPosition,
 };
 
 use image::{GenericImage, ImageBuffer, Rgba, RgbaImage};
-use imageproc::{drawing::draw_text_mut, rect::Rect};
+use imageproc::{
+    drawing::{draw_line_segment_mut, draw_text_mut},
+    rect::Rect,
+};
 use indicatif::ProgressBar;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use rayon::prelude::*;
 use rusttype::{point, Font, Point, PositionedGlyph, Scale};
-use std::{env, fs, path::Path};
+use std::{fs, path::Path};
 use textwrap::wrap;
 
 pub fn grid_desired_size(
@@ -44,14 +46,19 @@ pub fn total_delay_seconds(media_attributes: &MediaAttributes, args: &Args) -> f
     start_delay_seconds + end_delay_seconds
 }
 
-pub fn timestamp_generator(media_attributes: &MediaAttributes, args: &Args) -> Vec<String> {
+/// Seconds between evenly spaced captures.
+pub fn capture_interval(media_attributes: &MediaAttributes, args: &Args) -> f32 {
     let delay = total_delay_seconds(media_attributes, args);
-    let capture_interval = match &args.interval {
+    match &args.interval {
         Some(interval) => interval.as_secs() as f32,
         None => {
             (media_attributes.duration_seconds - delay) / (args.num_samples.unwrap() as f32 + 1.0)
         }
-    };
+    }
+}
+
+pub fn timestamp_generator(media_attributes: &MediaAttributes, args: &Args) -> Vec<String> {
+    let capture_interval = capture_interval(media_attributes, args);
 
     let mut time = (media_attributes.duration_seconds * args.start_delay_percent / 100.0).floor();
 
@@ -65,72 +72,146 @@ pub fn timestamp_generator(media_attributes: &MediaAttributes, args: &Args) -> V
         .collect()
 }
 
+/// One timestamp in the middle of every shot found by scene detection,
+/// keeping the longest shots when there are more than `num_samples`.
+/// Falls back to evenly spaced timestamps when there are fewer shots than
+/// grid cells.
+pub fn scene_timestamps(
+    media_attributes: &MediaAttributes,
+    frame_source: &dyn FrameSource,
+    args: &Args,
+) -> Result<Vec<String>, VcsrError> {
+    let duration = media_attributes.duration_seconds;
+    let start = (duration * args.start_delay_percent / 100.0).floor();
+    let end = duration - (duration * args.end_delay_percent / 100.0).floor();
+
+    let mut boundaries = vec![0.0];
+    boundaries.extend(frame_source.scene_changes(args.scene_threshold)?);
+    boundaries.push(duration);
+    let mut shots: Vec<(f32, f32)> = boundaries
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+        .filter(|(shot_start, shot_end)| {
+            let middle = (shot_start + shot_end) / 2.0;
+            middle >= start && middle <= end
+        })
+        .collect();
+
+    let num_selected = args.num_selected.unwrap() as usize;
+    if shots.len() < num_selected {
+        info!(
+            "found {} shots for {} captures, spacing captures evenly",
+            shots.len(),
+            num_selected
+        );
+        return Ok(timestamp_generator(media_attributes, args));
+    }
+
+    let num_samples = args.num_samples.unwrap() as usize;
+    if shots.len() > num_samples {
+        shots.sort_by(|a, b| (b.1 - b.0).partial_cmp(&(a.1 - a.0)).unwrap());
+        shots.truncate(num_samples);
+        shots.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    }
+    debug!("scene_timestamps - {} shots", shots.len());
+
+    Ok(shots
+        .iter()
+        .map(|(shot_start, shot_end)| {
+            MediaInfo::pretty_duration((shot_start + shot_end) / 2.0, false, true)
+        })
+        .collect())
+}
+
+/// `per_chapter` timestamps spaced evenly within every chapter.
+pub fn chapter_timestamps(chapters: &[ChapterMarker], per_chapter: u64) -> Vec<String> {
+    chapters
+        .iter()
+        .flat_map(|chapter| {
+            let step = (chapter.end - chapter.start) / (per_chapter as f32 + 1.0);
+            (1..=per_chapter).map(move |i| chapter.start + step * i as f32)
+        })
+        .map(|ts| MediaInfo::pretty_duration(ts, false, true))
+        .collect()
+}
+
 pub fn select_sharpest_images(
     media_attributes: &MediaAttributes,
-    media_capture: &MediaCapture,
+    frame_source: &dyn FrameSource,
     args: &Args,
     bar: &ProgressBar,
-) -> Result<(Vec<Frame>, Vec<Frame>), VcsrError> {
+) -> Result<Vec<Frame>, VcsrError> {
     let desired_size = grid_desired_size(
         &args.grid,
-        &media_attributes.dimensions,
+        &media_attributes.visible_dimensions(),
         Some(args.vcs_width),
         Some(args.grid_horizontal_spacing),
     );
 
-    let timestamps = if args.manual_timestamps.is_empty() {
+    let timestamps = if !args.manual_timestamps.is_empty() {
+        args.manual_timestamps.clone()
+    } else if args.scenes {
+        scene_timestamps(media_attributes, frame_source, args)?
+    } else if args.chapters && !media_attributes.chapters.is_empty() {
+        chapter_timestamps(&media_attributes.chapters, args.captures_per_chapter)
+    } else {
         timestamp_generator(media_attributes, &args)
+    };
+    let timestamps: Vec<f32> = timestamps
+        .iter()
+        .map(|ts| MediaInfo::pretty_to_seconds(ts))
+        .collect::<Result<_, _>>()?;
+    let timestamps = if args.frame_type.as_deref() == Some("key") {
+        snap_to_keyframes(timestamps, &frame_source.keyframes()?)
     } else {
-        args.manual_timestamps.clone()
+        timestamps
     };
 
-    let do_capture = |ts_tuple: (f32, String),
-                      width: u64,
-                      height: u64,
-                      suffix: &str,
-                      args: &Args|
-     -> Result<Frame, VcsrError> {
-        let rand_string: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
-        let mut dir = env::temp_dir();
-        let filename = format!("tmp{}{}", rand_string, suffix);
-        dir.push(&filename);
-        let full_path = dir.to_string_lossy().into_owned();
-        debug!("select_sharpest_images - media_capture.makecapture(ts_tuple.1 {}, width {width}, height {height}, full_path {full_path}))", ts_tuple.1);
-        media_capture.make_capture(&ts_tuple.1, width, height, Some(&full_path))?;
-        let mut blurriness = 1.0;
-        let mut avg_colour = 0.0;
-        if !args.fast {
-            blurriness = MediaCapture::compute_blurrines(&full_path)?;
-            avg_colour = MediaCapture::compute_avg_colour(&full_path)?;
+    // Waveforms and spectrograms have no sharpness or colour to choose by.
+    let is_audio = media_attributes.audio_stream.is_some();
+    let analyse = !args.fast && !is_audio;
+    let blurs: Result<Vec<Frame>, VcsrError> = match args.capture_mode {
+        CaptureMode::PerTimestamp => timestamps
+            .into_par_iter()
+            .map(|ts| {
+                let (ts, image) = capture_with_retries(frame_source, ts, &desired_size, args)?;
+                bar.inc(1);
+                Ok(analyse_capture(ts, image, analyse))
+            })
+            .collect(),
+        CaptureMode::SinglePass => {
+            debug!("select_sharpest_images - capture_all({:?})", timestamps);
+            match frame_source.capture_all(&timestamps, desired_size.x, desired_size.y) {
+                Ok(images) => Ok(timestamps
+                    .into_par_iter()
+                    .zip(images)
+                    .map(|(ts, image)| {
+                        let frame = analyse_capture(ts, image, analyse);
+                        bar.inc(1);
+                        frame
+                    })
+                    .collect()),
+                Err(err @ VcsrError::CaptureFailed { .. }) => {
+                    warn!("{}, capturing each timestamp separately", err);
+                    timestamps
+                        .into_par_iter()
+                        .map(|ts| {
+                            let (ts, image) =
+                                capture_with_retries(frame_source, ts, &desired_size, args)?;
+                            bar.inc(1);
+                            Ok(analyse_capture(ts, image, analyse))
+                        })
+                        .collect()
+                }
+                Err(err) => Err(err),
+            }
         }
-        Ok(Frame {
-            filename: full_path,
-            blurriness,
-            timestamp: ts_tuple.0,
-            avg_colour,
-        })
     };
-
-    let blurs: Result<Vec<Frame>, VcsrError> = timestamps
-        .into_par_iter()
-        .map(|ts| {
-            let result = do_capture(
-                (MediaInfo::pretty_to_seconds(&ts)?, ts),
-                desired_size.x,
-                desired_size.y,
-                if args.fast { ".jpg" } else { ".png" },
-                args,
-            );
-            bar.inc(1);
-            result
-        })
-        .collect();
     let mut time_sorted = blurs?;
     time_sorted.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
+    if is_audio {
+        return Ok(time_sorted);
+    }
 
     let num_groups = args.num_groups.unwrap();
     let mut selected_items: Vec<Frame> = vec![];
@@ -143,14 +224,179 @@ pub fn select_sharpest_images(
             }
         }
     } else {
-        selected_items = time_sorted.clone();
+        selected_items = time_sorted;
     };
 
     let selected_items = select_colour_variety(&mut selected_items, num_groups);
-    Ok((selected_items, time_sorted))
+    Ok(selected_items)
+}
+
+/// Find the black bars around the picture from `CROP_DETECT_SAMPLES`
+/// frames spread over the video. The crop keeps everything that is not a
+/// bar in any of them, so dark scenes do not cut into the picture. Returns
+/// `None` when there are no bars.
+pub fn detect_crop(
+    media_attributes: &MediaAttributes,
+    frame_source: &dyn FrameSource,
+    args: &Args,
+) -> Result<Option<Crop>, VcsrError> {
+    let dimensions = &media_attributes.dimensions;
+    let frame = Grid {
+        x: dimensions.display_width.ok_or(VcsrError::MediaError)?,
+        y: dimensions.display_height.ok_or(VcsrError::MediaError)?,
+    };
+    let sample_size = MediaInfo::desired_size(dimensions, Some(frame.x.min(CROP_DETECT_WIDTH)));
+
+    let start = (media_attributes.duration_seconds * args.start_delay_percent / 100.0).floor();
+    let interval = (media_attributes.duration_seconds
+        - total_delay_seconds(media_attributes, args))
+        / (CROP_DETECT_SAMPLES as f32 + 1.0);
+    let times: Vec<f32> = (1..=CROP_DETECT_SAMPLES)
+        .map(|i| start + interval * i as f32)
+        .collect();
+
+    let crops: Vec<Crop> = times
+        .into_par_iter()
+        .filter_map(
+            |time| match frame_source.capture(time, sample_size.x, sample_size.y) {
+                Ok(image) => Crop::of_image(&image, CROP_DETECT_LIMIT),
+                Err(err) => {
+                    debug!("cannot sample {} for crop detection: {}", time, err);
+                    None
+                }
+            },
+        )
+        .collect();
+    let crop = match crops.split_first() {
+        Some((first, rest)) => rest.iter().fold(*first, |crop, other| crop.union(other)),
+        None => return Ok(None),
+    };
+
+    let crop = crop.scale(&sample_size, &frame);
+    if crop.width == frame.x && crop.height == frame.y {
+        Ok(None)
+    } else {
+        Ok(Some(crop))
+    }
+}
+
+/// Moves every timestamp onto its nearest free keyframe so keyframe-only
+/// captures land exactly on a frame and are labelled with its real time,
+/// without two cells showing the same keyframe while there are enough.
+/// Timestamps are left untouched when no keyframes are known.
+fn snap_to_keyframes(timestamps: Vec<f32>, keyframes: &[f32]) -> Vec<f32> {
+    if keyframes.is_empty() {
+        warn!("no keyframes found, capturing at the requested timestamps");
+        return timestamps;
+    }
+    debug!("snapping timestamps to {} keyframes", keyframes.len());
+    let snapped = MediaCapture::distinct_keyframes(keyframes, &timestamps);
+    if keyframes.len() < timestamps.len() {
+        warn!(
+            "only {} keyframes for {} captures, repeating keyframes",
+            keyframes.len(),
+            timestamps.len()
+        );
+    }
+    snapped
+}
+
+/// Captures a frame at `timestamp`, retrying up to `--capture-retries`
+/// times, each a little earlier in the file, when ffmpeg or the FFmpeg
+/// libraries fail. A capture that timed out is not retried, as it would
+/// likely time out again. Returns the time actually captured so the
+/// thumbnail is labelled correctly. When every attempt fails,
+/// `--placeholder-on-failure` fills the cell instead of failing the whole
+/// sheet.
+fn capture_with_retries(
+    frame_source: &dyn FrameSource,
+    timestamp: f32,
+    desired_size: &Grid,
+    args: &Args,
+) -> Result<(f32, RgbaImage), VcsrError> {
+    let mut last_error = None;
+    for attempt in 0..=args.capture_retries {
+        let ts = (timestamp - attempt as f32 * CAPTURE_RETRY_NUDGE_SECONDS).max(0.0);
+        debug!(
+            "capture at {} ({} of {} attempts)",
+            ts,
+            attempt + 1,
+            args.capture_retries + 1
+        );
+        match frame_source.capture(ts, desired_size.x, desired_size.y) {
+            Ok(image) => return Ok((ts, image)),
+            Err(err @ VcsrError::Timeout { .. }) => {
+                warn!("{}", err);
+                last_error = Some(err);
+                break;
+            }
+            Err(err) if is_capture_failure(&err) => {
+                warn!("{}", err);
+                last_error = Some(err);
+                if ts == 0.0 {
+                    break;
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    match last_error {
+        Some(_) if args.placeholder_on_failure => Ok((
+            timestamp,
+            placeholder_image(desired_size.x as u32, desired_size.y as u32),
+        )),
+        Some(err) => Err(err),
+        None => unreachable!("at least one capture is attempted"),
+    }
+}
+
+/// Whether `err` is a failure to capture a single frame, rather than one
+/// that stops every capture, such as cancellation.
+#[cfg(feature = "libav")]
+fn is_capture_failure(err: &VcsrError) -> bool {
+    matches!(
+        err,
+        VcsrError::CaptureFailed { .. } | VcsrError::LibavError(_)
+    )
+}
+
+#[cfg(not(feature = "libav"))]
+fn is_capture_failure(err: &VcsrError) -> bool {
+    matches!(err, VcsrError::CaptureFailed { .. })
+}
+
+/// A dark grey cell crossed through, standing in for a capture ffmpeg could
+/// not produce.
+fn placeholder_image(width: u32, height: u32) -> RgbaImage {
+    let mut image = RgbaImage::from_pixel(width, height, Rgba([48, 48, 48, 255]));
+    let colour = Rgba([96, 96, 96, 255]);
+    let (right, bottom) = (width as f32 - 1.0, height as f32 - 1.0);
+    draw_line_segment_mut(&mut image, (0.0, 0.0), (right, bottom), colour);
+    draw_line_segment_mut(&mut image, (right, 0.0), (0.0, bottom), colour);
+    image
+}
+
+/// Measures blurriness and average colour of a capture.
+fn analyse_capture(timestamp: f32, image: RgbaImage, analyse: bool) -> Frame {
+    let mut blurriness = 1.0;
+    let mut avg_colour = 0.0;
+    if analyse {
+        blurriness = MediaCapture::compute_blurrines(&image);
+        avg_colour = MediaCapture::compute_avg_colour(&image);
+    }
+    Frame {
+        avg_colour,
+        blurriness,
+        image,
+        timestamp,
+    }
 }
 
 pub fn select_colour_variety(frames: &mut Vec<Frame>, num_selected: u64) -> Vec<Frame> {
+    if frames.is_empty() {
+        return vec![];
+    }
     frames.sort_by(|a, b| a.avg_colour.partial_cmp(&b.avg_colour).unwrap());
     let min_colour = frames.first().unwrap().avg_colour;
     let max_colour = frames.last().unwrap().avg_colour;
@@ -181,10 +427,12 @@ pub fn select_colour_variety(frames: &mut Vec<Frame>, num_selected: u64) -> Vec<
         }
     }
 
-    let missing_item_count = num_selected - selected_items.len() as u64;
+    // There may be fewer frames than cells, such as when keyframes run out.
+    let missing_item_count = (num_selected.saturating_sub(selected_items.len() as u64) as usize)
+        .min(unselected_items.len());
     if missing_item_count > 0 {
         unselected_items.sort_by(|a, b| a.blurriness.partial_cmp(&b.blurriness).unwrap());
-        selected_items.extend_from_slice(&unselected_items[0..missing_item_count as usize]);
+        selected_items.extend_from_slice(&unselected_items[0..missing_item_count]);
     }
 
     selected_items
@@ -233,6 +481,7 @@ pub fn max_line_length(
 
 pub fn prepare_metadata_text_lines(
     media_attributes: &MediaAttributes,
+    args: &Args,
     dimensions: &Dimensions,
     header_font: &Font,
     header_font_size: f32,
@@ -241,17 +490,89 @@ pub fn prepare_metadata_text_lines(
 ) -> Vec<String> {
     // TODO: template maybe
     let mut header_lines = vec![];
-    let template = format!(
+    let mut template = format!(
         r#"{filename}
         File size: {size}
-        Duration: {duration}
-        Dimensions: {sample_width}x{sample_height}"#,
+        Duration: {duration}"#,
         filename = media_attributes.filename,
         size = media_attributes.size,
         duration = media_attributes.duration,
-        sample_width = dimensions.display_width.unwrap(),
-        sample_height = dimensions.display_height.unwrap()
     );
+    if media_attributes.duration_source == Some(DurationSource::Packets) {
+        template.push_str(" (estimated)");
+    }
+    if let Some(frame_rate) = media_attributes.frame_rate {
+        if !media_attributes.variable_frame_rate {
+            template.push_str(&format!(
+                ", timecode {}",
+                frame_rate.timecode(media_attributes.duration_seconds)
+            ));
+        }
+    }
+    if media_attributes.start_time != 0.0 {
+        template.push_str(&format!(
+            ", starting at {}",
+            MediaInfo::pretty_time(media_attributes.start_time, true)
+        ));
+    }
+    if media_attributes.audio_stream.is_none() {
+        template.push_str(&format!(
+            "\nDimensions: {}x{}",
+            dimensions.display_width.unwrap(),
+            dimensions.display_height.unwrap()
+        ));
+    }
+    if let Some(crop) = &media_attributes.crop {
+        template.push_str(&format!(", cropped to {}x{}", crop.width, crop.height));
+    }
+    if media_attributes.hdr {
+        template.push_str(" (HDR)");
+    }
+    if let (Some(frame_rate), None) = (
+        media_attributes.frame_rate,
+        &media_attributes.image_sequence,
+    ) {
+        template.push_str(&format!("\nFrame rate: {} fps", frame_rate));
+        if media_attributes.variable_frame_rate {
+            template.push_str(" (variable)");
+        }
+    }
+    if let Some(sequence) = &media_attributes.image_sequence {
+        template.push_str(&format!(
+            "\nFrames: {}-{}",
+            sequence.first_frame,
+            sequence.first_frame + sequence.frame_count - 1
+        ));
+        if let Some(frame_rate) = media_attributes.frame_rate {
+            template.push_str(&format!(" at {} fps", frame_rate));
+        }
+    }
+    if args.chapters && !media_attributes.chapters.is_empty() {
+        let chapters: Vec<String> = media_attributes
+            .chapters
+            .iter()
+            .enumerate()
+            .map(|(i, chapter)| {
+                let start = MediaInfo::pretty_duration(chapter.start, false, false);
+                match &chapter.title {
+                    Some(title) => format!("{} {}", start, title),
+                    None => format!("{} Chapter {}", start, i + 1),
+                }
+            })
+            .collect();
+        template.push_str(&format!("\nChapters: {}", chapters.join(", ")));
+    }
+    // Audio tracks are all there is to show of an audio-only file.
+    if args.stream_details || media_attributes.audio_stream.is_some() {
+        for track in &media_attributes.audio_tracks {
+            template.push_str(&format!("\n{}", track));
+        }
+    }
+    if args.stream_details {
+        for track in &media_attributes.subtitle_tracks {
+            template.push_str(&format!("\n{}", track));
+        }
+    }
 
     let template_lines = template
         .split("\n")
@@ -314,6 +635,79 @@ pub fn compute_timestamp_position(
     (upper_left, size)
 }
 
+/// The font for timestamps and burnt in subtitles, `--timestamp-font` or
+/// else the bundled Roboto.
+fn timestamp_font<'a>(args: &Args) -> Result<Font<'a>, VcsrError> {
+    match &args.timestamp_font {
+        Some(font_path_str) => load_font(font_path_str),
+        None => {
+            let data = include_bytes!("../resources/Roboto-Regular.ttf").to_vec();
+            Font::try_from_vec(data).ok_or(VcsrError::RustTypeError)
+        }
+    }
+}
+
+/// Draws the subtitle cue shown at each frame's timestamp along the bottom
+/// of the frame, wrapping long cues to the frame width.
+pub fn burn_subtitles(
+    frames: &mut [Frame],
+    cues: &[SubtitleCue],
+    args: &Args,
+) -> Result<(), VcsrError> {
+    let font = timestamp_font(args)?;
+    let scale = Scale::uniform(args.subtitle_font_size);
+    let line_height = (args.subtitle_font_size * 1.2) as i32;
+    let margin = args.timestamp_horizontal_margin;
+    let font_colour = decode_hex(&args.timestamp_font_colour)?;
+    let border_colour = decode_hex(&args.timestamp_border_colour)?;
+
+    for frame in frames.iter_mut() {
+        let cue = match cues
+            .iter()
+            .find(|cue| cue.start <= frame.timestamp && frame.timestamp < cue.end)
+        {
+            Some(cue) => cue,
+            None => continue,
+        };
+
+        let width = frame.image.width() as u64;
+        let mut lines = vec![];
+        for line in cue.text.lines() {
+            let max_length = max_line_length(
+                line,
+                &font,
+                args.subtitle_font_size,
+                margin,
+                width,
+                Some(line),
+            );
+            lines.extend(wrap(line, max_length.max(1)).into_iter().map(String::from));
+        }
+
+        let mut y = frame.image.height() as i32
+            - args.timestamp_vertical_margin as i32
+            - line_height * lines.len() as i32;
+        for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+            let (text_width, _) = get_text_size(&font, scale, line);
+            let x = (width as i32 - text_width as i32) / 2;
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter() {
+                draw_text_mut(
+                    &mut frame.image,
+                    border_colour,
+                    x + dx,
+                    y + dy,
+                    scale,
+                    &font,
+                    line,
+                );
+            }
+            draw_text_mut(&mut frame.image, font_colour, x, y, scale, &font, line);
+            y += line_height;
+        }
+    }
+    Ok(())
+}
+
 pub fn load_font<'a>(font_path_str: &str) -> Result<Font<'a>, VcsrError> {
     let font_path = Path::new(font_path_str);
     if font_path.exists() {
@@ -392,7 +786,7 @@ pub fn compose_contact_sheet(
     let dimensions = &media_attributes.dimensions;
     let desired_size = grid_desired_size(
         &args.grid,
-        &dimensions,
+        &media_attributes.visible_dimensions(),
         Some(args.vcs_width),
         Some(args.grid_horizontal_spacing),
     );
@@ -408,18 +802,18 @@ pub fn compose_contact_sheet(
             Font::try_from_vec(data).ok_or(VcsrError::RustTypeError)?
         }
     };
-    let timestamp_font = match &args.timestamp_font {
-        Some(font_path_str) => load_font(&font_path_str)?,
-        None => {
-            let data = include_bytes!("../resources/Roboto-Regular.ttf").to_vec();
-            Font::try_from_vec(data).ok_or(VcsrError::RustTypeError)?
-        }
-    };
+    let timestamp_font = timestamp_font(args)?;
     let timestamp_font_scale = Scale::uniform(args.timestamp_font_size);
     let timestamp_border_colour = decode_hex(&args.timestamp_border_colour)?;
 
+    let chapters: &[ChapterMarker] = if args.chapters {
+        &media_attributes.chapters
+    } else {
+        &[]
+    };
     let header_lines = prepare_metadata_text_lines(
         &media_attributes,
+        args,
         &dimensions,
         &header_font,
         args.metadata_font_size,
@@ -483,7 +877,7 @@ pub fn compose_contact_sheet(
     let mut blurred = image::imageops::blur(&mut rect, 3.0);
     frames.sort_by(|a, b| a.timestamp.partial_cmp(&b.timestamp).unwrap());
     for (i, frame) in frames.iter().enumerate() {
-        let mut f = image::open(&Path::new(&frame.filename)).unwrap().to_rgba8();
+        let mut f = frame.image.clone();
 
         putalpha(&mut f, args.capture_alpha);
 
@@ -493,14 +887,34 @@ pub fn compose_contact_sheet(
         image::imageops::overlay(&mut image, &mut f, x as i64, y as i64);
 
         if args.show_timestamp {
-            let timestamp_time = MediaInfo::pretty_duration(frame.timestamp, true, false);
+            // Image sequences are labelled with the number of the image
+            // captured, and animated images with the index of the frame.
+            let timestamp_time = match (
+                &media_attributes.image_sequence,
+                media_attributes.frame_rate,
+            ) {
+                (Some(sequence), Some(frame_rate)) => {
+                    (sequence.first_frame + frame_rate.frame_number(frame.timestamp)).to_string()
+                }
+                (None, Some(frame_rate)) if media_attributes.animated => {
+                    frame_rate.frame_number(frame.timestamp).to_string()
+                }
+                _ => args
+                    .timestamp_base
+                    .label(frame.timestamp, media_attributes.start_time),
+            };
             let _timestamp_duration =
                 MediaInfo::pretty_duration(media_attributes.duration_seconds, true, true);
             let _parsed_time = MediaInfo::parse_duration(frame.timestamp);
             let _parsed_duraton = MediaInfo::parse_duration(media_attributes.duration_seconds);
 
             // TODO: Handlebar
-            let timestamp_text = format!("{}", timestamp_time);
+            let timestamp_text = match MediaInfo::chapter_at(chapters, frame.timestamp)
+                .and_then(|chapter| chapter.title.as_ref())
+            {
+                Some(title) => format!("{} {}", timestamp_time, title),
+                None => format!("{}", timestamp_time),
+            };
             let text_size = get_text_size(
                 &timestamp_font,
                 Scale::uniform(args.timestamp_font_size),
@@ -748,3 +1162,29 @@ where
         colour,
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(timestamp: f32, avg_colour: f32) -> Frame {
+        Frame {
+            avg_colour,
+            blurriness: timestamp,
+            image: RgbaImage::new(1, 1),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn fewer_keyframes_than_cells_keep_every_cell() {
+        let timestamps: Vec<f32> = (0..16).map(|i| i as f32 * 10.0).collect();
+        let snapped = snap_to_keyframes(timestamps, &[0.0, 60.0, 120.0]);
+        assert_eq!(snapped.len(), 16);
+        assert!(snapped.iter().all(|time| [0.0, 60.0, 120.0].contains(time)));
+
+        let mut frames = vec![frame(0.0, 10.0), frame(60.0, 10.0), frame(120.0, 200.0)];
+        assert_eq!(select_colour_variety(&mut frames, 16).len(), 3);
+        assert!(select_colour_variety(&mut vec![], 16).is_empty());
+    }
+}
@@ -0,0 +1,149 @@
+//! Running ffmpeg and ffprobe with a timeout, and cancelling every running
+//! child when the user interrupts vcsr.
+use crate::errors::VcsrError;
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How often running children are checked for exit, timeout and
+/// cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Temporary files to remove when vcsr exits without unwinding.
+static TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Ask every running and future child process to be killed. Processing
+/// then fails with `VcsrError::Cancelled`.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Have `path` removed by `remove_temp_files` until it is forgotten.
+pub fn add_temp_file(path: &Path) {
+    if let Ok(mut files) = TEMP_FILES.lock() {
+        files.push(path.to_path_buf());
+    }
+}
+
+/// Stop tracking `path`, once it has been removed.
+pub fn forget_temp_file(path: &Path) {
+    if let Ok(mut files) = TEMP_FILES.lock() {
+        files.retain(|file| file != path);
+    }
+}
+
+/// Remove every temporary file still tracked, before exiting with
+/// `std::process::exit`, which runs no destructors.
+pub fn remove_temp_files() {
+    if let Ok(mut files) = TEMP_FILES.lock() {
+        for file in files.drain(..) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// Run `command` to completion and collect its output, killing it if it
+/// runs longer than `timeout` or processing is cancelled.
+pub fn run(command: &mut Command, timeout: Option<Duration>) -> Result<Output, VcsrError> {
+    if is_cancelled() {
+        return Err(VcsrError::Cancelled);
+    }
+
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain both pipes while waiting so a chatty child cannot block on a
+    // full pipe.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Output {
+                status,
+                stdout: stdout.join().unwrap_or_default(),
+                stderr: stderr.join().unwrap_or_default(),
+            });
+        }
+
+        let error = if is_cancelled() {
+            Some(VcsrError::Cancelled)
+        } else {
+            timeout
+                .filter(|timeout| started.elapsed() >= *timeout)
+                .map(|timeout| VcsrError::Timeout {
+                    program: program.clone(),
+                    seconds: timeout.as_secs_f32(),
+                })
+        };
+        if let Some(error) = error {
+            warn!("killing {} (pid {})", program, child.id());
+            child.kill()?;
+            child.wait()?;
+            return Err(error);
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_collects_output() {
+        let output = run(Command::new("echo").arg("vcsr"), None).unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"vcsr\n");
+    }
+
+    #[test]
+    fn temp_files_are_removed() {
+        let path = std::env::temp_dir().join(format!("vcsr-temp-test-{}", std::process::id()));
+        fs::write(&path, b"spool").unwrap();
+        add_temp_file(&path);
+        remove_temp_files();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn run_kills_hung_child() {
+        let started = Instant::now();
+        let result = run(
+            Command::new("sleep").arg("10"),
+            Some(Duration::from_millis(100)),
+        );
+        assert!(matches!(result, Err(VcsrError::Timeout { .. })));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}